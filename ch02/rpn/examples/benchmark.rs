@@ -0,0 +1,73 @@
+use rpn::RpnEngine;
+
+use std::cell::RefCell;
+use std::str::FromStr;
+use std::time::Instant;
+use std::{env, f64};
+
+const EXP: &str = "x @ x @ * y @ y @ * + sqrt 3.4 2.5 / 1.6 * - x @ y @ max /";
+
+fn main() {
+    if let Some(n) = env::args().nth(1) {
+        let n = usize::from_str(&n).expect("error parsing argument");
+        run_evals(n);
+    } else {
+        eprintln!(
+            "Usage {} <number of evaluations>",
+            env::args().next().unwrap()
+        );
+        std::process::exit(1);
+    }
+}
+
+fn run_evals(n: usize) {
+    println!("evaluating \"{}\" {} times", EXP, n);
+
+    let interp_duration = time_eval(&interpret, n, "interpret");
+    let compiled_duration = time_eval(&compiled, n, "compiled");
+
+    println!("speed up: {:.2}x", interp_duration / compiled_duration);
+}
+
+// 毎回文字列から評価する
+fn interpret() -> impl Fn(f64, f64) -> f64 {
+    let engine = RefCell::new(RpnEngine::default());
+    move |x, y| {
+        let mut engine = engine.borrow_mut();
+        engine.set_var("x", x);
+        engine.set_var("y", y);
+        engine.eval(EXP).expect("Failed to evaluate: ")
+    }
+}
+
+// 一度だけコンパイルしたプログラムを評価する
+fn compiled() -> impl Fn(f64, f64) -> f64 {
+    let program = rpn::compile(EXP).expect("Failed to compile: ");
+    assert_eq!(program.variables(), ["x", "y"]);
+    move |x, y| program.eval(&[x, y]).expect("Failed to evaluate: ")
+}
+
+fn time_eval<G, F>(make_evaluator: &G, n: usize, name: &str) -> f64
+where
+    G: Fn() -> F,
+    F: Fn(f64, f64) -> f64,
+{
+    let evaluator = make_evaluator();
+    let start = Instant::now();
+    let mut sum = 0.0;
+    for i in 0..n {
+        sum += evaluator(i as f64, (n - i) as f64);
+    }
+    let dur = start.elapsed();
+
+    let nano_secs = dur.subsec_nanos() as f64 + dur.as_secs() as f64 * 1e9_f64;
+    println!(
+        "{}: evaluated {} times in {} seconds (sum: {:.4})",
+        name,
+        n,
+        nano_secs / 1e9,
+        sum
+    );
+
+    nano_secs
+}
@@ -0,0 +1,770 @@
+//! rpn は逆ポーランド記法 (RPN) の式を評価する機能を提供します。
+//! 演算子は [`RpnEngine`](struct.RpnEngine.html) に名前と引数の数で登録します。
+//!
+//! 演算子のほかに次の語が使えます。
+//! * `dup` `swap` `drop` `over` `rot`: Forth と同じスタック操作
+//! * `x !`: スタックの先頭を取り出して変数 `x` に格納する
+//! * `x @`: 変数 `x` の値をスタックに積む
+//! * `: name ... ;`: `...` を本体とする語 `name` を定義する
+//!
+//! 変数と定義は評価器が持つ環境に格納され、評価をまたいで保持されます。
+//! 演算子でない名前の直後に `!` か `@` が続けば、同じ名前の語が定義されていても
+//! 変数へのアクセスになります。
+//!
+//! 評価器は数値の型 ([`Number`](number/trait.Number.html)) について汎用で、
+//! `f64` のほかに桁あふれを検査する `i64`、正確な有理数、任意精度の10進小数を選べます。
+
+pub mod number;
+mod program;
+
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::Arc;
+
+pub use crate::number::{ArithError, BigDecimal, BigRational, Number};
+pub use crate::program::Program;
+
+/// 評価エラーの種類
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpnErrorKind {
+    /// 数値でも登録済みの演算子でもないトークンがきた
+    UnknownOperator(String),
+    /// 演算子に必要な数のオペランドがスタックにない
+    StackUnderflow,
+    /// 評価が終わったのにスタックに2つ以上の値が残っている
+    LeftoverOperands(usize),
+    /// 値が格納されていない変数を読み出そうとした
+    UndefinedVariable(String),
+    /// 定義の本体で未定義の語を使おうとした
+    UndefinedWord(String),
+    /// 定義の本体で定義中の語そのものを使おうとした
+    RecursiveDefinition(String),
+    /// 定義の名前がない、名前が不正、`;` で閉じられていない、または定義が入れ子になっている
+    InvalidDefinition,
+    /// 演算の途中で桁あふれや 0 除算が起きた
+    Arithmetic(ArithError),
+}
+
+/// 評価エラー。何番目のトークン (0始まり) で、入力の何バイト目で起きたかを持つ
+///
+/// 入力の終端で起きたエラーは、トークン数と入力の長さを位置とする
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpnError {
+    pub kind: RpnErrorKind,
+    pub index: usize,
+    pub offset: usize,
+}
+
+impl RpnError {
+    pub fn new(kind: RpnErrorKind, index: usize, offset: usize) -> Self {
+        Self {
+            kind,
+            index,
+            offset,
+        }
+    }
+}
+
+impl fmt::Display for RpnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::RpnErrorKind::*;
+        write!(f, "token {} (byte {}): ", self.index, self.offset)?;
+        match &self.kind {
+            UnknownOperator(op) => write!(f, "unknown operator '{}'", op),
+            StackUnderflow => write!(f, "stack underflow"),
+            LeftoverOperands(n) => write!(f, "{} operands left on the stack", n),
+            UndefinedVariable(name) => write!(f, "undefined variable '{}'", name),
+            UndefinedWord(name) => write!(f, "undefined word '{}'", name),
+            RecursiveDefinition(name) => write!(f, "'{}' is defined recursively", name),
+            InvalidDefinition => write!(f, "invalid definition"),
+            Arithmetic(e) => e.fmt(f),
+        }
+    }
+}
+
+impl StdError for RpnError {}
+
+/// 演算子の本体。スタックから取り出した引数を、積まれた順に受け取る
+pub type OpFn<N> = Arc<dyn Fn(&[N]) -> Result<N, ArithError> + Send + Sync>;
+
+/// 登録された演算子。引数の数と本体を持つ
+pub struct Operator<N> {
+    pub arity: usize,
+    pub fun: OpFn<N>,
+}
+
+impl<N> Clone for Operator<N> {
+    fn clone(&self) -> Self {
+        Self {
+            arity: self.arity,
+            fun: self.fun.clone(),
+        }
+    }
+}
+
+impl<N> fmt::Debug for Operator<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Operator")
+            .field("arity", &self.arity)
+            .finish()
+    }
+}
+
+/// 演算子の表を持つ RPN の評価器
+///
+/// `RpnEngine::default()` は標準の数学ライブラリを登録した状態で作られる。
+/// 何も登録されていない評価器が欲しいときは `RpnEngine::new()` を使う。
+/// 数値の型 `N` を省略すると `f64` になる
+///
+/// # Example
+///
+/// ```
+/// use rpn::{BigRational, RpnEngine};
+///
+/// let mut engine: RpnEngine = RpnEngine::default();
+/// engine.register2("hypot", |x, y| x.hypot(y));
+/// assert_eq!(engine.eval("3 4 hypot"), Ok(5.0));
+///
+/// let mut exact = RpnEngine::<BigRational>::default();
+/// let ans = exact.eval("0.1 0.2 +").unwrap();
+/// assert_eq!(ans.to_string(), "3/10");
+/// ```
+#[derive(Debug, Clone)]
+pub struct RpnEngine<N = f64> {
+    ops: HashMap<String, Operator<N>>,
+    vars: HashMap<String, N>,
+    words: HashMap<String, Arc<[Word<N>]>>,
+}
+
+impl<N: Number> Default for RpnEngine<N> {
+    fn default() -> Self {
+        let mut engine = Self::new();
+        engine.register_std();
+        engine
+    }
+}
+
+impl<N: Number> RpnEngine<N> {
+    /// 演算子が1つも登録されていない評価器を作る
+    pub fn new() -> Self {
+        Self {
+            ops: HashMap::new(),
+            vars: HashMap::new(),
+            words: HashMap::new(),
+        }
+    }
+
+    /// 引数 `arity` 個の、失敗しうる演算子を登録する。同名の演算子があれば置き換える
+    pub fn register_checked<F>(&mut self, name: &str, arity: usize, fun: F)
+    where
+        F: Fn(&[N]) -> Result<N, ArithError> + Send + Sync + 'static,
+    {
+        let op = Operator {
+            arity,
+            fun: Arc::new(fun),
+        };
+        self.ops.insert(name.to_string(), op);
+    }
+
+    /// 引数 `arity` 個の演算子を登録する。同名の演算子があれば置き換える
+    pub fn register<F>(&mut self, name: &str, arity: usize, fun: F)
+    where
+        F: Fn(&[N]) -> N + Send + Sync + 'static,
+    {
+        self.register_checked(name, arity, move |args| Ok(fun(args)));
+    }
+
+    /// 単項演算子を登録する
+    pub fn register1<F>(&mut self, name: &str, fun: F)
+    where
+        F: Fn(N) -> N + Send + Sync + 'static,
+    {
+        self.register(name, 1, move |args| fun(args[0].clone()));
+    }
+
+    /// 二項演算子を登録する。`x y op` は `fun(x, y)` になる
+    pub fn register2<F>(&mut self, name: &str, fun: F)
+    where
+        F: Fn(N, N) -> N + Send + Sync + 'static,
+    {
+        self.register(name, 2, move |args| fun(args[0].clone(), args[1].clone()));
+    }
+
+    /// 登録済みの演算子を探す
+    pub fn operator(&self, name: &str) -> Option<&Operator<N>> {
+        self.ops.get(name)
+    }
+
+    /// 変数の値を返す
+    pub fn var(&self, name: &str) -> Option<N> {
+        self.vars.get(name).cloned()
+    }
+
+    /// 変数に値を格納する
+    pub fn set_var(&mut self, name: &str, value: N) {
+        self.vars.insert(name.to_string(), value);
+    }
+
+    /// 標準の数学ライブラリを登録する
+    ///
+    /// `+ - * / % ^ neg abs min max` のほかに、数値の型に固有の演算子も登録する
+    pub fn register_std(&mut self) {
+        self.register_checked("+", 2, |args| args[0].try_add(&args[1]));
+        self.register_checked("-", 2, |args| args[0].try_sub(&args[1]));
+        self.register_checked("*", 2, |args| args[0].try_mul(&args[1]));
+        self.register_checked("/", 2, |args| args[0].try_div(&args[1]));
+        self.register_checked("%", 2, |args| args[0].try_rem(&args[1]));
+        self.register_checked("^", 2, |args| args[0].try_pow(&args[1]));
+        self.register_checked("neg", 1, |args| args[0].try_neg());
+        self.register_checked("abs", 1, |args| {
+            if args[0] < N::zero() {
+                args[0].try_neg()
+            } else {
+                Ok(args[0].clone())
+            }
+        });
+        self.register2("min", |x, y| if y < x { y } else { x });
+        self.register2("max", |x, y| if y > x { y } else { x });
+        N::register_extra(self);
+    }
+
+    /// 式を評価し、最後にスタックに残った1つの値を返す
+    pub fn eval(&mut self, exp: &str) -> Result<N, RpnError> {
+        let mut stack = Vec::new();
+        let count = self.exec(exp, &mut stack)?;
+        if stack.len() > 1 {
+            let kind = RpnErrorKind::LeftoverOperands(stack.len());
+            return Err(RpnError::new(kind, count, exp.len()));
+        }
+        stack
+            .pop()
+            .ok_or_else(|| RpnError::new(RpnErrorKind::StackUnderflow, count, exp.len()))
+    }
+
+    /// 与えられたスタックの上で式を実行し、読んだトークンの数を返す
+    ///
+    /// `eval` と違い、実行後にスタックに残った値の数は検査しない
+    pub fn exec(&mut self, exp: &str, stack: &mut Vec<N>) -> Result<usize, RpnError> {
+        let program = self.translate(exp)?;
+        for instr in &program {
+            self.run(&instr.word, stack)
+                .map_err(|kind| RpnError::new(kind, instr.index, instr.offset))?;
+        }
+        Ok(tokenize(exp).count())
+    }
+
+    /// 式を、繰り返し評価できるプログラムにコンパイルする
+    ///
+    /// トークンの分割と演算子の検索はここで一度だけ行う。式の中の定義は評価器に登録されるが、
+    /// 変数は評価器の環境ではなく [`Program::eval`](struct.Program.html#method.eval)
+    /// に渡す値を使う
+    pub fn compile(&mut self, exp: &str) -> Result<Program<N>, RpnError> {
+        let instrs = self.translate(exp)?;
+        Ok(Program::new(&instrs, tokenize(exp).count(), exp.len()))
+    }
+
+    // 式を語の列に変換する。定義 (: name ... ;) はここで評価器に登録する
+    fn translate(&mut self, exp: &str) -> Result<Vec<Instr<N>>, RpnError> {
+        let tokens: Vec<_> = tokenize(exp).collect();
+        let mut program = Vec::new();
+        let mut pos = 0;
+        while pos < tokens.len() {
+            if tokens[pos].1 == ":" {
+                pos = self.define(&tokens, pos, exp.len())?;
+            } else {
+                let (instr, next) = self.resolve(&tokens, pos, None)?;
+                program.push(instr);
+                pos = next;
+            }
+        }
+        Ok(program)
+    }
+
+    // tokens[start] の ":" から始まる定義を登録し、";" の次の位置を返す
+    //
+    // 本体はこの時点で存在する語に解決されるので、後から語を定義し直しても
+    // 定義済みの語の意味は変わらない
+    fn define(
+        &mut self,
+        tokens: &[(usize, &str)],
+        start: usize,
+        len: usize,
+    ) -> Result<usize, RpnError> {
+        let unterminated = RpnError::new(RpnErrorKind::InvalidDefinition, tokens.len(), len);
+        let (offset, name) = *tokens.get(start + 1).ok_or_else(|| unterminated.clone())?;
+        if name == ":" || name == ";" || N::parse(name).is_some() {
+            return Err(RpnError::new(
+                RpnErrorKind::InvalidDefinition,
+                start + 1,
+                offset,
+            ));
+        }
+        let mut body = Vec::new();
+        let mut pos = start + 2;
+        loop {
+            match tokens.get(pos) {
+                None => return Err(unterminated),
+                Some(&(_, ";")) => break,
+                Some(_) => {
+                    let (instr, next) = self.resolve(tokens, pos, Some(name))?;
+                    body.push(instr.word);
+                    pos = next;
+                }
+            }
+        }
+        self.words.insert(name.to_string(), body.into());
+        Ok(pos + 1)
+    }
+
+    // tokens[pos] を語に変換し、次に読む位置とあわせて返す
+    //
+    // defining には定義中の語の名前を渡す。未知の名前は定義の外なら UnknownOperator、
+    // 定義の中なら UndefinedWord になる
+    fn resolve(
+        &self,
+        tokens: &[(usize, &str)],
+        pos: usize,
+        defining: Option<&str>,
+    ) -> Result<(Instr<N>, usize), RpnError> {
+        use self::RpnErrorKind::*;
+        let (offset, token) = tokens[pos];
+        let at = |word| Ok((Instr::new(word, pos, offset), pos + 1));
+        match N::parse(token) {
+            Some(Ok(num)) => return at(Word::Num(num)),
+            Some(Err(e)) => return Err(RpnError::new(Arithmetic(e), pos, offset)),
+            None => {}
+        }
+        // 演算子でない名前の直後に ! か @ が続けば変数へのアクセスとする。
+        // 同じ名前の語より優先するので、語を定義しても変数は使える
+        let is_name =
+            token != ":" && StackWord::from_token(token).is_none() && !self.ops.contains_key(token);
+        if is_name {
+            match tokens.get(pos + 1) {
+                Some(&(o, "!")) => {
+                    let word = Word::Store(token.to_string());
+                    return Ok((Instr::new(word, pos + 1, o), pos + 2));
+                }
+                Some(&(_, "@")) => {
+                    let word = Word::Fetch(token.to_string());
+                    return Ok((Instr::new(word, pos, offset), pos + 2));
+                }
+                _ => {}
+            }
+        }
+        if defining == Some(token) {
+            let kind = RecursiveDefinition(token.to_string());
+            return Err(RpnError::new(kind, pos, offset));
+        }
+        if let Some(body) = self.words.get(token) {
+            return at(Word::Call(body.clone()));
+        }
+        if let Some(word) = StackWord::from_token(token) {
+            return at(Word::Stack(word));
+        }
+        if let Some(op) = self.ops.get(token) {
+            return at(Word::Op(op.clone()));
+        }
+        let kind = match defining {
+            Some(_) if token == ":" => InvalidDefinition,
+            Some(_) => UndefinedWord(token.to_string()),
+            None => UnknownOperator(token.to_string()),
+        };
+        Err(RpnError::new(kind, pos, offset))
+    }
+
+    // 語を1つ実行する
+    fn run(&mut self, word: &Word<N>, stack: &mut Vec<N>) -> Result<(), RpnErrorKind> {
+        use self::RpnErrorKind::*;
+        match word {
+            Word::Num(num) => stack.push(num.clone()),
+            Word::Stack(word) => word.apply(stack).ok_or(StackUnderflow)?,
+            Word::Op(op) => apply(stack, op)?,
+            Word::Call(body) => {
+                for word in body.iter() {
+                    self.run(word, stack)?;
+                }
+            }
+            Word::Store(name) => {
+                let value = stack.pop().ok_or(StackUnderflow)?;
+                self.set_var(name, value);
+            }
+            Word::Fetch(name) => {
+                let value = self
+                    .var(name)
+                    .ok_or_else(|| UndefinedVariable(name.clone()))?;
+                stack.push(value);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 標準の数学ライブラリで式を評価する
+pub fn rpn(exp: &str) -> Result<f64, RpnError> {
+    rpn_as(exp)
+}
+
+/// 標準の数学ライブラリで式をコンパイルする
+///
+/// # Example
+///
+/// ```
+/// use rpn::compile;
+///
+/// let program = compile("x @ dup * y @ +").unwrap();
+/// assert_eq!(program.variables(), ["x", "y"]);
+/// assert_eq!(program.eval(&[3.0, 1.0]), Ok(10.0));
+/// assert_eq!(program.eval(&[4.0, 2.0]), Ok(18.0));
+/// ```
+pub fn compile(exp: &str) -> Result<Program, RpnError> {
+    RpnEngine::default().compile(exp)
+}
+
+/// 数値の型 `N` を指定し、標準の数学ライブラリで式を評価する
+///
+/// # Example
+///
+/// ```
+/// use rpn::{rpn_as, BigDecimal};
+///
+/// let ans = rpn_as::<BigDecimal>("6.1 5.2 4.3 * + 3.4 2.5 / 1.6 * -").unwrap();
+/// assert_eq!(ans.to_string(), "26.284");
+/// ```
+pub fn rpn_as<N: Number>(exp: &str) -> Result<N, RpnError> {
+    RpnEngine::<N>::default().eval(exp)
+}
+
+// 入力中の各トークンとその開始バイト位置を返す
+fn tokenize(exp: &str) -> impl Iterator<Item = (usize, &str)> {
+    let base = exp.as_ptr() as usize;
+    exp.split_whitespace()
+        .map(move |token| (token.as_ptr() as usize - base, token))
+}
+
+// コンパイル済みの語
+#[derive(Debug, Clone)]
+enum Word<N> {
+    Num(N),
+    Stack(StackWord),
+    Op(Operator<N>),
+    /// ユーザ定義の語。本体は定義した時点の語に解決済み
+    Call(Arc<[Word<N>]>),
+    Store(String),
+    Fetch(String),
+}
+
+// 入力中の位置 (トークンの番号とバイト位置) をつけた語
+#[derive(Debug, Clone)]
+struct Instr<N> {
+    word: Word<N>,
+    index: usize,
+    offset: usize,
+}
+
+impl<N> Instr<N> {
+    fn new(word: Word<N>, index: usize, offset: usize) -> Self {
+        Self {
+            word,
+            index,
+            offset,
+        }
+    }
+}
+
+// Forth 由来のスタック操作語
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StackWord {
+    /// a -> a a
+    Dup,
+    /// a b -> b a
+    Swap,
+    /// a ->
+    Drop,
+    /// a b -> a b a
+    Over,
+    /// a b c -> b c a
+    Rot,
+}
+
+impl StackWord {
+    fn from_token(token: &str) -> Option<Self> {
+        use self::StackWord::*;
+        match token {
+            "dup" => Some(Dup),
+            "swap" => Some(Swap),
+            "drop" => Some(Drop),
+            "over" => Some(Over),
+            "rot" => Some(Rot),
+            _ => None,
+        }
+    }
+
+    // 必要な数の値がスタックになければ None を返す
+    fn apply<N: Clone>(self, stack: &mut Vec<N>) -> Option<()> {
+        use self::StackWord::*;
+        let len = stack.len();
+        match self {
+            Dup => stack.push(stack.last()?.clone()),
+            Swap => stack.swap(len.checked_sub(2)?, len - 1),
+            Drop => {
+                stack.pop()?;
+            }
+            Over => stack.push(stack[len.checked_sub(2)?].clone()),
+            Rot => stack[len.checked_sub(3)?..].rotate_left(1),
+        }
+        Some(())
+    }
+}
+
+// スタックから op の引数を取り出して結果を積む
+fn apply<N>(stack: &mut Vec<N>, op: &Operator<N>) -> Result<(), RpnErrorKind> {
+    let start = stack
+        .len()
+        .checked_sub(op.arity)
+        .ok_or(RpnErrorKind::StackUnderflow)?;
+    let z = (op.fun)(&stack[start..]).map_err(RpnErrorKind::Arithmetic)?;
+    stack.truncate(start);
+    stack.push(z);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rpn_works() {
+        let ans = rpn("6.1 5.2 4.3 * + 3.4 2.5 / 1.6 * -").unwrap();
+        assert_eq!(format!("{:.4}", ans), "26.2840");
+    }
+
+    #[test]
+    fn rpn_unknown_operator() {
+        assert_eq!(
+            rpn("1 2 foo"),
+            Err(RpnError::new(
+                RpnErrorKind::UnknownOperator("foo".to_string()),
+                2,
+                4
+            ))
+        );
+    }
+
+    #[test]
+    fn rpn_stack_underflow() {
+        assert_eq!(
+            rpn("1  +"),
+            Err(RpnError::new(RpnErrorKind::StackUnderflow, 1, 3))
+        );
+        assert_eq!(
+            rpn(""),
+            Err(RpnError::new(RpnErrorKind::StackUnderflow, 0, 0))
+        );
+    }
+
+    #[test]
+    fn rpn_leftover_operands() {
+        assert_eq!(
+            rpn("1 2 3 +"),
+            Err(RpnError::new(RpnErrorKind::LeftoverOperands(2), 4, 7))
+        );
+    }
+
+    #[test]
+    fn std_library_works() {
+        assert_eq!(rpn("16 sqrt"), Ok(4.0));
+        assert_eq!(rpn("2 10 ^"), Ok(1024.0));
+        assert_eq!(rpn("7 3 %"), Ok(1.0));
+        assert_eq!(rpn("3 neg"), Ok(-3.0));
+        assert_eq!(rpn("3 5 min 4 max"), Ok(4.0));
+        assert_eq!(rpn("2 3 4 fma"), Ok(10.0));
+        assert_eq!(rpn("pi"), Ok(std::f64::consts::PI));
+    }
+
+    #[test]
+    fn stack_words_work() {
+        assert_eq!(rpn("3 dup *"), Ok(9.0));
+        assert_eq!(rpn("1 2 swap -"), Ok(1.0));
+        assert_eq!(rpn("1 2 drop"), Ok(1.0));
+        assert_eq!(rpn("1 2 over + *"), Ok(3.0));
+        assert_eq!(rpn("1 2 3 rot - *"), Ok(4.0));
+        assert_eq!(
+            rpn("1 swap"),
+            Err(RpnError::new(RpnErrorKind::StackUnderflow, 1, 2))
+        );
+    }
+
+    #[test]
+    fn variables_persist_across_calls() {
+        let mut engine = RpnEngine::default();
+        let mut stack = Vec::new();
+        assert_eq!(engine.exec("2 x !\n3 y !", &mut stack), Ok(6));
+        assert!(stack.is_empty());
+        assert_eq!(engine.eval("x @ y @ *"), Ok(6.0));
+        assert_eq!(engine.var("x"), Some(2.0));
+
+        engine.set_var("x", 10.0);
+        assert_eq!(engine.eval("x @ 1 + x ! x @"), Ok(11.0));
+    }
+
+    #[test]
+    fn variable_errors() {
+        let mut engine: RpnEngine = RpnEngine::default();
+        assert_eq!(
+            engine.eval("z @"),
+            Err(RpnError::new(
+                RpnErrorKind::UndefinedVariable("z".to_string()),
+                0,
+                0
+            ))
+        );
+        assert_eq!(
+            engine.eval("z !"),
+            Err(RpnError::new(RpnErrorKind::StackUnderflow, 1, 2))
+        );
+        assert_eq!(
+            engine.eval("1 z"),
+            Err(RpnError::new(
+                RpnErrorKind::UnknownOperator("z".to_string()),
+                1,
+                2
+            ))
+        );
+    }
+
+    #[test]
+    fn user_defined_words() {
+        let mut engine = RpnEngine::default();
+        assert_eq!(engine.eval(": sq dup * ; 3 sq"), Ok(9.0));
+        assert_eq!(engine.eval(": cube dup sq * ;\n2 cube sq"), Ok(64.0));
+        assert_eq!(
+            engine.eval(": inc x @ 1 + x ! ; 1 x ! inc inc x @"),
+            Ok(3.0)
+        );
+
+        // 定義済みの語は、使っている語を定義し直しても意味が変わらない
+        assert_eq!(engine.eval(": sq 0 ; 2 cube"), Ok(8.0));
+    }
+
+    #[test]
+    fn variables_take_precedence_over_words() {
+        let mut engine = RpnEngine::default();
+        assert_eq!(engine.eval(": x 1 ; 2 x ! x x @ +"), Ok(3.0));
+        assert_eq!(engine.var("x"), Some(2.0));
+        // 定義の中でも、定義中の語と同じ名前の変数を使える
+        assert_eq!(engine.eval(": y y @ 1 + y ! ; 5 y ! y y @"), Ok(6.0));
+        // 演算子の名前は変数にならない
+        assert_eq!(
+            engine.eval("1 2 dup !"),
+            Err(RpnError::new(
+                RpnErrorKind::UnknownOperator("!".to_string()),
+                3,
+                8
+            ))
+        );
+    }
+
+    #[test]
+    fn definition_errors() {
+        use crate::RpnErrorKind::*;
+        let mut engine: RpnEngine = RpnEngine::default();
+        assert_eq!(
+            engine.eval(": f dup g ;"),
+            Err(RpnError::new(UndefinedWord("g".to_string()), 3, 8))
+        );
+        assert_eq!(
+            engine.eval(": f 1 f + ;"),
+            Err(RpnError::new(RecursiveDefinition("f".to_string()), 3, 6))
+        );
+        assert_eq!(
+            engine.eval(": f dup *"),
+            Err(RpnError::new(InvalidDefinition, 4, 9))
+        );
+        assert_eq!(
+            engine.eval(": 1 dup ;"),
+            Err(RpnError::new(InvalidDefinition, 1, 2))
+        );
+        assert_eq!(
+            engine.eval(": f : @ ;"),
+            Err(RpnError::new(InvalidDefinition, 2, 4))
+        );
+        assert_eq!(
+            engine.eval(": f : g ; ;"),
+            Err(RpnError::new(InvalidDefinition, 2, 4))
+        );
+        assert_eq!(
+            engine.eval("1 ;"),
+            Err(RpnError::new(UnknownOperator(";".to_string()), 1, 2))
+        );
+        assert_eq!(
+            engine.eval("f"),
+            Err(RpnError::new(UnknownOperator("f".to_string()), 0, 0))
+        );
+    }
+
+    #[test]
+    fn number_modes() {
+        let exp = "6.1 5.2 4.3 * + 3.4 2.5 / 1.6 * -";
+        let exact = rpn_as::<BigRational>(exp).unwrap();
+        assert_eq!(exact.to_string(), "6571/250");
+        assert_eq!(rpn_as::<BigDecimal>(exp).unwrap().to_string(), "26.284");
+
+        assert_eq!(rpn_as::<i64>("7 2 / 3 ^"), Ok(27));
+        assert_eq!(
+            rpn_as::<i64>("1.5"),
+            Err(RpnError::new(
+                RpnErrorKind::UnknownOperator("1.5".to_string()),
+                0,
+                0
+            ))
+        );
+    }
+
+    #[test]
+    fn arithmetic_errors() {
+        use crate::ArithError::*;
+        assert_eq!(
+            rpn_as::<i64>("9223372036854775807 1 +"),
+            Err(RpnError::new(RpnErrorKind::Arithmetic(Overflow), 2, 22))
+        );
+        assert_eq!(
+            rpn_as::<BigRational>("1 0 /"),
+            Err(RpnError::new(
+                RpnErrorKind::Arithmetic(DivisionByZero),
+                2,
+                4
+            ))
+        );
+        assert_eq!(rpn("1 0 /"), Ok(f64::INFINITY));
+
+        // 巨大な指数は、数値を作る前にエラーにする
+        assert_eq!(
+            rpn_as::<BigRational>("1 1e2000000000 +"),
+            Err(RpnError::new(RpnErrorKind::Arithmetic(Overflow), 1, 2))
+        );
+        assert_eq!(
+            rpn_as::<BigDecimal>("2 1000000000 ^"),
+            Err(RpnError::new(RpnErrorKind::Arithmetic(Overflow), 2, 13))
+        );
+        let mut engine = RpnEngine::<BigRational>::default();
+        assert_eq!(
+            engine.eval(": 1e9999 dup ;"),
+            Err(RpnError::new(RpnErrorKind::InvalidDefinition, 1, 2))
+        );
+    }
+
+    #[test]
+    fn register_user_defined_operator() {
+        let mut engine = RpnEngine::new();
+        assert!(engine.eval("1 2 +").is_err());
+
+        engine.register2("+", |x, y| x + y);
+        engine.register("avg3", 3, |args| args.iter().sum::<f64>() / 3.0);
+        assert_eq!(engine.eval("1 2 + 6 9 avg3"), Ok(6.0));
+        assert_eq!(
+            engine.eval("1 2 avg3"),
+            Err(RpnError::new(RpnErrorKind::StackUnderflow, 2, 4))
+        );
+    }
+}
@@ -1,36 +1,226 @@
+use std::collections::VecDeque;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+
+use rpn::{BigDecimal, BigRational, Number, RpnEngine, RpnError};
+
 fn main() {
-    let exp = "6.1 5.2 4.3 * + 3.4 2.5 / 1.6 * -";
-    let ans = rpn(exp);
-    debug_assert_eq!("26.2840", format!("{:.4}", ans));
-    println!("{}", ans);
+    // 1. コマンドラインで指定した数値の型とスクリプトのファイル名を読み込む
+    let mut mode = String::from("f64");
+    let mut path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-m" | "--mode" => mode = args.next().unwrap_or_else(|| usage()),
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => usage(),
+        }
+    }
+
+    // 2. 型ごとに評価器を作って、すべての行を実行する
+    let result = match mode.as_str() {
+        "f64" => run::<f64>(path),
+        "i64" => run::<i64>(path),
+        "rational" => run::<BigRational>(path),
+        "decimal" => run::<BigDecimal>(path),
+        _ => usage(),
+    };
+    match result {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "Usage: {} [-m f64|i64|rational|decimal] [FILE]",
+        env::args().next().unwrap()
+    );
+    std::process::exit(1);
 }
 
-fn rpn(exp: &str) -> f64 {
-    let mut stack = Vec::new();
-    for token in exp.split_whitespace() {
-        if let Ok(num) = token.parse::<f64>() {
-            stack.push(num);
-        } else {
-            match token {
-                "+" => apply2(&mut stack, |x, y| x + y),
-                "-" => apply2(&mut stack, |x, y| x - y),
-                "*" => apply2(&mut stack, |x, y| x * y),
-                "/" => apply2(&mut stack, |x, y| x / y),
-                _ => panic!("Unknow operator: {}", token),
+// ファイルが指定されていればそれを、なければ標準入力を読む
+// 標準入力が端末のときだけプロンプトを出して対話的に動く
+fn run<N: Number>(path: Option<String>) -> io::Result<bool> {
+    let mut session = Session::<N>::new();
+    match path {
+        Some(path) => session.run(BufReader::new(File::open(path)?), false),
+        None => {
+            let stdin = io::stdin();
+            let interactive = stdin.is_terminal();
+            session.run(stdin.lock(), interactive)
+        }
+    }
+}
+
+const PROMPT: &str = "> ";
+
+/// undo で取り消せる行の数
+const HISTORY_LIMIT: usize = 100;
+
+/// 行をまたいでスタックと評価器の状態を保持する
+///
+/// 1行ごとに実行前の状態を履歴に積み、`undo` でその行を取り消せる。
+/// 履歴は直近の HISTORY_LIMIT 行分だけ残すので、長いスクリプトでもメモリは増え続けない。
+/// エラーになった行は何もしなかったことになる
+struct Session<N> {
+    engine: RpnEngine<N>,
+    stack: Vec<N>,
+    history: VecDeque<(RpnEngine<N>, Vec<N>)>,
+}
+
+impl<N: Number> Session<N> {
+    fn new() -> Self {
+        Self {
+            engine: RpnEngine::default(),
+            stack: Vec::new(),
+            history: VecDeque::new(),
+        }
+    }
+
+    /// 入力の各行を実行し、行ごとにスタックを表示する。すべての行が成功したら true を返す
+    fn run(&mut self, input: impl BufRead, interactive: bool) -> io::Result<bool> {
+        let mut ok = true;
+        let mut lines = input.lines().enumerate();
+        loop {
+            if interactive {
+                print!("{}", PROMPT);
+                io::stdout().flush()?;
+            }
+            let (lineno, line) = match lines.next() {
+                Some((lineno, line)) => (lineno + 1, line?),
+                None => break,
+            };
+            if line.trim().is_empty() {
+                continue;
             }
+            match self.line(&line) {
+                Ok(()) => println!("{}", self.show_stack()),
+                Err(e) if interactive => {
+                    // プロンプトの下にエラーの位置を示す
+                    eprintln!("{}^", " ".repeat(PROMPT.len() + e.offset));
+                    eprintln!("{}", e);
+                }
+                Err(e) => {
+                    eprintln!("line {}: {}", lineno, e);
+                    ok = false;
+                }
+            }
+        }
+        if interactive {
+            println!();
         }
+        Ok(ok)
+    }
+
+    /// 1行を実行する。`clear` はスタックを空にし、`undo` は直前の行を取り消す
+    fn line(&mut self, line: &str) -> Result<(), RpnError> {
+        match line.trim() {
+            "clear" => {
+                self.save((self.engine.clone(), self.stack.clone()));
+                self.stack.clear();
+            }
+            "undo" => {
+                if let Some((engine, stack)) = self.history.pop_back() {
+                    self.engine = engine;
+                    self.stack = stack;
+                }
+            }
+            _ => {
+                let saved = (self.engine.clone(), self.stack.clone());
+                if let Err(e) = self.engine.exec(line, &mut self.stack) {
+                    (self.engine, self.stack) = saved;
+                    return Err(e);
+                }
+                self.save(saved);
+            }
+        }
+        Ok(())
+    }
+
+    // 実行前の状態を履歴に積む。上限を超えたら最も古いものを捨てる
+    fn save(&mut self, state: (RpnEngine<N>, Vec<N>)) {
+        if self.history.len() == HISTORY_LIMIT {
+            self.history.pop_front();
+        }
+        self.history.push_back(state);
+    }
+
+    fn show_stack(&self) -> String {
+        let values: Vec<_> = self.stack.iter().map(|v| v.to_string()).collect();
+        values.join(" ")
     }
-    stack.pop().expect("Stack underflow")
 }
 
-fn apply2<F>(stack: &mut Vec<f64>, fun: F)
-where
-    F: Fn(f64, f64) -> f64,
-{
-    if let (Some(y), Some(x)) = (stack.pop(), stack.pop()) {
-        let z = fun(x, y);
-        stack.push(z);
-    } else {
-        panic!("Stack underflow");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn stack_is_kept_between_lines() {
+        let mut session = Session::<f64>::new();
+        assert_eq!(session.line("1 2"), Ok(()));
+        assert_eq!(session.line("3 +"), Ok(()));
+        assert_eq!(session.show_stack(), "1 5");
+        assert_eq!(session.line("x ! x @ dup"), Ok(()));
+        assert_eq!(session.show_stack(), "1 5 5");
+    }
+
+    #[test]
+    fn undo_and_clear() {
+        let mut session = Session::<i64>::new();
+        session.line("1 2").unwrap();
+        session.line(": sq dup * ; sq").unwrap();
+        assert_eq!(session.show_stack(), "1 4");
+        session.line("clear").unwrap();
+        assert_eq!(session.show_stack(), "");
+        session.line("undo").unwrap();
+        assert_eq!(session.show_stack(), "1 4");
+        session.line("undo").unwrap();
+        assert_eq!(session.show_stack(), "1 2");
+        // 取り消した行の定義も消える
+        assert!(session.line("sq").is_err());
+        session.line("undo").unwrap();
+        session.line("undo").unwrap();
+        assert_eq!(session.show_stack(), "");
+    }
+
+    #[test]
+    fn history_is_limited() {
+        let mut session = Session::<i64>::new();
+        for i in 0..HISTORY_LIMIT + 50 {
+            session.line(&i.to_string()).unwrap();
+        }
+        assert_eq!(session.history.len(), HISTORY_LIMIT);
+        for _ in 0..HISTORY_LIMIT + 50 {
+            session.line("undo").unwrap();
+        }
+        // 最も古い50行は取り消せない
+        assert_eq!(session.stack.len(), 50);
+    }
+
+    #[test]
+    fn failed_line_has_no_effect() {
+        let mut session = Session::<i64>::new();
+        session.line("1 2").unwrap();
+        assert!(session.line("3 x ! + + +").is_err());
+        assert_eq!(session.show_stack(), "1 2");
+        assert!(session.line("x @").is_err());
+        session.line("undo").unwrap();
+        assert_eq!(session.show_stack(), "");
+    }
+
+    #[test]
+    fn run_script() {
+        let mut session = Session::<f64>::new();
+        let script = Cursor::new("1 2 +\n\nfoo\n4 *\n");
+        assert!(!session.run(script, false).unwrap());
+        assert_eq!(session.show_stack(), "12");
     }
 }
@@ -0,0 +1,434 @@
+//! 評価器が扱う数値の型
+//!
+//! 次の型に [`Number`](trait.Number.html) を実装している
+//! * `f64`: これまでどおりの浮動小数点数
+//! * `i64`: 桁あふれをエラーにする整数。割り算は 0 方向への切り捨て
+//! * [`BigRational`](type.BigRational.html): 任意精度の有理数。四則演算はすべて正確
+//! * [`BigDecimal`](struct.BigDecimal.html): 任意精度の10進小数。割り算は有効数字100桁で丸める
+
+use std::fmt;
+use std::str::FromStr;
+
+pub use bigdecimal::BigDecimal;
+pub use num_rational::BigRational;
+
+use num_bigint::BigInt;
+use num_traits::{Pow, ToPrimitive, Zero};
+
+use crate::RpnEngine;
+
+/// 算術エラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArithError {
+    /// 結果が型で表せる範囲を超えた
+    Overflow,
+    /// 0 で割った
+    DivisionByZero,
+    /// 引数が演算の定義域の外にある
+    Domain,
+}
+
+impl fmt::Display for ArithError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::ArithError::*;
+        match self {
+            Overflow => write!(f, "arithmetic overflow"),
+            DivisionByZero => write!(f, "division by zero"),
+            Domain => write!(f, "argument out of domain"),
+        }
+    }
+}
+
+/// 評価器のスタックに積める数値
+///
+/// 標準の演算子 `+ - * / % ^ neg abs min max` はこのトレイトのメソッドで実装される。
+/// 型に固有の演算子は `register_extra` で登録する
+pub trait Number:
+    Clone + fmt::Debug + fmt::Display + PartialEq + PartialOrd + Send + Sync + 'static
+{
+    /// トークンを数値として読む。数値でなければ None を、数値だが表せなければ
+    /// Some(Err) を返す
+    fn parse(token: &str) -> Option<Result<Self, ArithError>>;
+
+    fn zero() -> Self;
+
+    fn try_add(&self, other: &Self) -> Result<Self, ArithError>;
+    fn try_sub(&self, other: &Self) -> Result<Self, ArithError>;
+    fn try_mul(&self, other: &Self) -> Result<Self, ArithError>;
+    fn try_div(&self, other: &Self) -> Result<Self, ArithError>;
+    fn try_rem(&self, other: &Self) -> Result<Self, ArithError>;
+    fn try_neg(&self) -> Result<Self, ArithError>;
+    fn try_pow(&self, exp: &Self) -> Result<Self, ArithError>;
+
+    /// 型に固有の演算子を登録する
+    fn register_extra(_engine: &mut RpnEngine<Self>) {}
+}
+
+impl Number for f64 {
+    fn parse(token: &str) -> Option<Result<Self, ArithError>> {
+        token.parse().ok().map(Ok)
+    }
+
+    fn zero() -> Self {
+        0.0
+    }
+
+    // 浮動小数点数は IEEE 754 にしたがい、inf や NaN を返してエラーにはしない
+    fn try_add(&self, other: &Self) -> Result<Self, ArithError> {
+        Ok(self + other)
+    }
+
+    fn try_sub(&self, other: &Self) -> Result<Self, ArithError> {
+        Ok(self - other)
+    }
+
+    fn try_mul(&self, other: &Self) -> Result<Self, ArithError> {
+        Ok(self * other)
+    }
+
+    fn try_div(&self, other: &Self) -> Result<Self, ArithError> {
+        Ok(self / other)
+    }
+
+    fn try_rem(&self, other: &Self) -> Result<Self, ArithError> {
+        Ok(self % other)
+    }
+
+    fn try_neg(&self) -> Result<Self, ArithError> {
+        Ok(-self)
+    }
+
+    fn try_pow(&self, exp: &Self) -> Result<Self, ArithError> {
+        Ok(self.powf(*exp))
+    }
+
+    fn register_extra(engine: &mut RpnEngine<Self>) {
+        engine.register2("min", f64::min);
+        engine.register2("max", f64::max);
+        engine.register2("atan2", f64::atan2);
+
+        engine.register1("sqrt", f64::sqrt);
+        engine.register1("exp", f64::exp);
+        engine.register1("ln", f64::ln);
+        engine.register1("log10", f64::log10);
+        engine.register1("sin", f64::sin);
+        engine.register1("cos", f64::cos);
+        engine.register1("tan", f64::tan);
+        engine.register1("floor", f64::floor);
+        engine.register1("ceil", f64::ceil);
+        engine.register1("round", f64::round);
+
+        engine.register("fma", 3, |args| args[0].mul_add(args[1], args[2]));
+        engine.register("pi", 0, |_| std::f64::consts::PI);
+        engine.register("e", 0, |_| std::f64::consts::E);
+    }
+}
+
+impl Number for i64 {
+    fn parse(token: &str) -> Option<Result<Self, ArithError>> {
+        token.parse().ok().map(Ok)
+    }
+
+    fn zero() -> Self {
+        0
+    }
+
+    fn try_add(&self, other: &Self) -> Result<Self, ArithError> {
+        self.checked_add(*other).ok_or(ArithError::Overflow)
+    }
+
+    fn try_sub(&self, other: &Self) -> Result<Self, ArithError> {
+        self.checked_sub(*other).ok_or(ArithError::Overflow)
+    }
+
+    fn try_mul(&self, other: &Self) -> Result<Self, ArithError> {
+        self.checked_mul(*other).ok_or(ArithError::Overflow)
+    }
+
+    fn try_div(&self, other: &Self) -> Result<Self, ArithError> {
+        if *other == 0 {
+            return Err(ArithError::DivisionByZero);
+        }
+        self.checked_div(*other).ok_or(ArithError::Overflow)
+    }
+
+    fn try_rem(&self, other: &Self) -> Result<Self, ArithError> {
+        if *other == 0 {
+            return Err(ArithError::DivisionByZero);
+        }
+        self.checked_rem(*other).ok_or(ArithError::Overflow)
+    }
+
+    fn try_neg(&self) -> Result<Self, ArithError> {
+        self.checked_neg().ok_or(ArithError::Overflow)
+    }
+
+    fn try_pow(&self, exp: &Self) -> Result<Self, ArithError> {
+        if *exp < 0 {
+            return Err(ArithError::Domain);
+        }
+        let exp = u32::try_from(*exp).map_err(|_| ArithError::Overflow)?;
+        self.checked_pow(exp).ok_or(ArithError::Overflow)
+    }
+}
+
+impl Number for BigRational {
+    // "6.1" や "-2.5e-3" のような10進表記を、丸めずにそのまま有理数にする
+    fn parse(token: &str) -> Option<Result<Self, ArithError>> {
+        Some(parse_decimal(token)?.map(|(mantissa, exp)| {
+            let ten = BigRational::from_integer(BigInt::from(10));
+            BigRational::from_integer(mantissa) * Pow::pow(ten, exp)
+        }))
+    }
+
+    fn zero() -> Self {
+        Zero::zero()
+    }
+
+    fn try_add(&self, other: &Self) -> Result<Self, ArithError> {
+        Ok(self + other)
+    }
+
+    fn try_sub(&self, other: &Self) -> Result<Self, ArithError> {
+        Ok(self - other)
+    }
+
+    fn try_mul(&self, other: &Self) -> Result<Self, ArithError> {
+        Ok(self * other)
+    }
+
+    fn try_div(&self, other: &Self) -> Result<Self, ArithError> {
+        if other.is_zero() {
+            return Err(ArithError::DivisionByZero);
+        }
+        Ok(self / other)
+    }
+
+    fn try_rem(&self, other: &Self) -> Result<Self, ArithError> {
+        if other.is_zero() {
+            return Err(ArithError::DivisionByZero);
+        }
+        Ok(self % other)
+    }
+
+    fn try_neg(&self) -> Result<Self, ArithError> {
+        Ok(-self)
+    }
+
+    // 指数は整数に限る
+    fn try_pow(&self, exp: &Self) -> Result<Self, ArithError> {
+        if !exp.is_integer() {
+            return Err(ArithError::Domain);
+        }
+        let exp = exp.to_integer().to_i32().ok_or(ArithError::Overflow)?;
+        if exp < 0 && self.is_zero() {
+            return Err(ArithError::DivisionByZero);
+        }
+        // 0 と ±1 以外では、結果の分子か分母がおよそ bits * |exp| ビットになる
+        let bits = self.numer().bits().max(self.denom().bits());
+        if bits > 1 && bits.saturating_mul(exp.unsigned_abs().into()) > MAX_BITS {
+            return Err(ArithError::Overflow);
+        }
+        Ok(Pow::pow(self, exp))
+    }
+}
+
+impl Number for BigDecimal {
+    fn parse(token: &str) -> Option<Result<Self, ArithError>> {
+        // ほかの型と同じ10進表記だけを受け付けるよう、先に形を確かめる
+        if let Err(e) = parse_decimal(token)? {
+            return Some(Err(e));
+        }
+        BigDecimal::from_str(token).ok().map(Ok)
+    }
+
+    fn zero() -> Self {
+        Zero::zero()
+    }
+
+    fn try_add(&self, other: &Self) -> Result<Self, ArithError> {
+        Ok(self + other)
+    }
+
+    fn try_sub(&self, other: &Self) -> Result<Self, ArithError> {
+        Ok(self - other)
+    }
+
+    fn try_mul(&self, other: &Self) -> Result<Self, ArithError> {
+        Ok(self * other)
+    }
+
+    fn try_div(&self, other: &Self) -> Result<Self, ArithError> {
+        if other.is_zero() {
+            return Err(ArithError::DivisionByZero);
+        }
+        Ok(self / other)
+    }
+
+    fn try_rem(&self, other: &Self) -> Result<Self, ArithError> {
+        if other.is_zero() {
+            return Err(ArithError::DivisionByZero);
+        }
+        Ok(self % other)
+    }
+
+    fn try_neg(&self) -> Result<Self, ArithError> {
+        Ok(-self)
+    }
+
+    // 指数は整数に限る
+    fn try_pow(&self, exp: &Self) -> Result<Self, ArithError> {
+        if !exp.is_integer() {
+            return Err(ArithError::Domain);
+        }
+        let exp = exp.to_i64().ok_or(ArithError::Overflow)?;
+        if exp < 0 && self.is_zero() {
+            return Err(ArithError::DivisionByZero);
+        }
+        // 0 と ±1 以外では、結果の仮数がおよそ (仮数のビット数 + 小数点の位置の桁数) * |exp|
+        // ビットになる。10進1桁は4ビット未満
+        let (mantissa, scale) = self.as_bigint_and_exponent();
+        let bits = mantissa.bits() + scale.unsigned_abs() * 4;
+        if !(scale == 0 && bits <= 1) && bits.saturating_mul(exp.unsigned_abs()) > MAX_BITS {
+            return Err(ArithError::Overflow);
+        }
+        Ok(self.powi(exp))
+    }
+
+    fn register_extra(engine: &mut RpnEngine<Self>) {
+        engine.register_checked("sqrt", 1, |args| args[0].sqrt().ok_or(ArithError::Domain));
+        engine.register1("round", |x| x.round(0));
+    }
+}
+
+// 指数表記やべき乗で作れる数の大きさの上限 (10進で約4000桁)。巨大な指数を書くだけで
+// 計算やメモリを使い尽くせないように、これを超える数は Overflow にする
+const MAX_EXPONENT: u32 = 4096;
+const MAX_BITS: u64 = MAX_EXPONENT as u64 * 10 / 3;
+
+// [+-]digits[.digits][(e|E)[+-]digits] の形の10進表記を、仮数と10の指数に分ける。
+// 指数の絶対値が MAX_EXPONENT を超えれば Some(Err) を返す
+fn parse_decimal(token: &str) -> Option<Result<(BigInt, i32), ArithError>> {
+    let (body, exp) = match token.find(['e', 'E']) {
+        Some(i) => (&token[..i], parse_exponent(&token[i + 1..])?),
+        None => (token, Ok(0)),
+    };
+    let (sign, body) = match body.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, body.strip_prefix('+').unwrap_or(body)),
+    };
+    let (int, frac) = body.split_once('.').unwrap_or((body, ""));
+    if int.is_empty() && frac.is_empty() {
+        return None;
+    }
+    if !int.chars().chain(frac.chars()).all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let exp = match exp {
+        Ok(exp) => exp,
+        Err(e) => return Some(Err(e)),
+    };
+    let digits = BigInt::from_str(&format!("{}{}", int, frac)).ok()?;
+    let exp = exp.checked_sub(i32::try_from(frac.len()).ok()?)?;
+    let mantissa = if sign < 0 { -digits } else { digits };
+    Some(Ok((mantissa, exp)))
+}
+
+// 指数部 [+-]digits を読む
+fn parse_exponent(token: &str) -> Option<Result<i32, ArithError>> {
+    let digits = token.strip_prefix(['+', '-']).unwrap_or(token);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(match token.parse::<i32>() {
+        Ok(exp) if exp.unsigned_abs() <= MAX_EXPONENT => Ok(exp),
+        _ => Err(ArithError::Overflow),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ratio(n: i64, d: i64) -> BigRational {
+        BigRational::new(BigInt::from(n), BigInt::from(d))
+    }
+
+    #[test]
+    fn parse_rational_exactly() {
+        assert_eq!(BigRational::parse("6.1"), Some(Ok(ratio(61, 10))));
+        assert_eq!(BigRational::parse("-2.5e-3"), Some(Ok(ratio(-1, 400))));
+        assert_eq!(BigRational::parse("+12E2"), Some(Ok(ratio(1200, 1))));
+        assert_eq!(BigRational::parse(".5"), Some(Ok(ratio(1, 2))));
+        assert_eq!(BigRational::parse("."), None);
+        assert_eq!(BigRational::parse("1e"), None);
+        assert_eq!(BigRational::parse("1e+"), None);
+        assert_eq!(BigRational::parse("inf"), None);
+        assert_eq!(BigRational::parse("+"), None);
+    }
+
+    #[test]
+    fn reject_huge_exponents() {
+        for token in ["1e2000000000", "1e-4097", "1e99999999999", "0.5E+5000"] {
+            assert_eq!(
+                BigRational::parse(token),
+                Some(Err(ArithError::Overflow)),
+                "{}",
+                token
+            );
+            assert_eq!(
+                BigDecimal::parse(token),
+                Some(Err(ArithError::Overflow)),
+                "{}",
+                token
+            );
+        }
+        assert!(matches!(BigRational::parse("1e4096"), Some(Ok(_))));
+        assert!(matches!(BigDecimal::parse("-1e-4096"), Some(Ok(_))));
+        assert_eq!(BigRational::parse("1e2x"), None);
+
+        let huge = ratio(1_000_000_000, 1);
+        assert_eq!(ratio(2, 1).try_pow(&huge), Err(ArithError::Overflow));
+        assert_eq!(
+            ratio(1, 2).try_pow(&-huge.clone()),
+            Err(ArithError::Overflow)
+        );
+        assert_eq!(ratio(-1, 1).try_pow(&huge), Ok(ratio(1, 1)));
+        assert!(ratio(2, 1).try_pow(&ratio(4000, 1)).is_ok());
+
+        let two = BigDecimal::from(2);
+        let huge = BigDecimal::from(1_000_000_000);
+        assert_eq!(two.try_pow(&huge), Err(ArithError::Overflow));
+        assert_eq!(
+            BigDecimal::from_str("0.5").unwrap().try_pow(&huge),
+            Err(ArithError::Overflow)
+        );
+        assert_eq!(
+            BigDecimal::from_str("1e1").unwrap().try_pow(&huge),
+            Err(ArithError::Overflow)
+        );
+        assert_eq!(BigDecimal::from(1).try_pow(&huge), Ok(BigDecimal::from(1)));
+        assert!(two.try_pow(&BigDecimal::from(4000)).is_ok());
+    }
+
+    #[test]
+    fn checked_integer_arithmetic() {
+        assert_eq!(i64::MAX.try_add(&1), Err(ArithError::Overflow));
+        assert_eq!(i64::MIN.try_neg(), Err(ArithError::Overflow));
+        assert_eq!(7.try_div(&0), Err(ArithError::DivisionByZero));
+        assert_eq!(7.try_rem(&0), Err(ArithError::DivisionByZero));
+        assert_eq!(2.try_pow(&-1), Err(ArithError::Domain));
+        assert_eq!(2.try_pow(&62), Ok(1 << 62));
+        assert_eq!(2.try_pow(&63), Err(ArithError::Overflow));
+    }
+
+    #[test]
+    fn rational_pow() {
+        assert_eq!(ratio(2, 3).try_pow(&ratio(-2, 1)), Ok(ratio(9, 4)));
+        assert_eq!(ratio(2, 3).try_pow(&ratio(1, 2)), Err(ArithError::Domain));
+        assert_eq!(
+            ratio(0, 1).try_pow(&ratio(-1, 1)),
+            Err(ArithError::DivisionByZero)
+        );
+    }
+}
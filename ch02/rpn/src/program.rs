@@ -0,0 +1,171 @@
+use crate::{apply, Instr, Number, Operator, RpnError, RpnErrorKind, StackWord, Word};
+
+/// コンパイル済みの式
+///
+/// ユーザ定義の語は展開され、変数は番号で参照される平らな命令列になっている。
+/// [`RpnEngine::compile`](struct.RpnEngine.html#method.compile) で作る
+#[derive(Debug, Clone)]
+pub struct Program<N = f64> {
+    code: Vec<Code<N>>,
+    // code と同じ並びで、各命令の元になったトークンの番号とバイト位置
+    locs: Vec<(usize, usize)>,
+    variables: Vec<String>,
+    count: usize,
+    len: usize,
+}
+
+// 平らな命令
+#[derive(Debug, Clone)]
+enum Code<N> {
+    Push(N),
+    Stack(StackWord),
+    Apply(Operator<N>),
+    Store(usize),
+    Fetch(usize),
+}
+
+impl<N: Number> Program<N> {
+    // count と len は元の式のトークン数と長さ。式の終端で起きたエラーの位置に使う
+    pub(crate) fn new(instrs: &[Instr<N>], count: usize, len: usize) -> Self {
+        let mut program = Self {
+            code: Vec::new(),
+            locs: Vec::new(),
+            variables: Vec::new(),
+            count,
+            len,
+        };
+        for instr in instrs {
+            program.emit(&instr.word, (instr.index, instr.offset));
+        }
+        program
+    }
+
+    // 語を命令に変換して追加する。ユーザ定義の語は呼び出し位置に展開する
+    fn emit(&mut self, word: &Word<N>, loc: (usize, usize)) {
+        let code = match word {
+            Word::Num(num) => Code::Push(num.clone()),
+            Word::Stack(word) => Code::Stack(*word),
+            Word::Op(op) => Code::Apply(op.clone()),
+            Word::Call(body) => {
+                for word in body.iter() {
+                    self.emit(word, loc);
+                }
+                return;
+            }
+            Word::Store(name) => Code::Store(self.slot(name)),
+            Word::Fetch(name) => Code::Fetch(self.slot(name)),
+        };
+        self.code.push(code);
+        self.locs.push(loc);
+    }
+
+    // 変数の番号を返す。初めて見る名前なら番号を振る
+    fn slot(&mut self, name: &str) -> usize {
+        match self.variables.iter().position(|v| v == name) {
+            Some(slot) => slot,
+            None => {
+                self.variables.push(name.to_string());
+                self.variables.len() - 1
+            }
+        }
+    }
+
+    /// プログラムが使う変数の名前。`eval` に渡す値はこの順に並べる
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    /// 変数に `bindings` の値を束縛してプログラムを実行し、最後にスタックに残った1つの値を返す
+    ///
+    /// `bindings` が `variables()` より短ければ、残りの変数は未定義のまま実行する
+    pub fn eval(&self, bindings: &[N]) -> Result<N, RpnError> {
+        let mut slots: Vec<Option<N>> = (0..self.variables.len())
+            .map(|slot| bindings.get(slot).cloned())
+            .collect();
+        let mut stack = Vec::new();
+        for (code, &(index, offset)) in self.code.iter().zip(&self.locs) {
+            self.step(code, &mut stack, &mut slots)
+                .map_err(|kind| RpnError::new(kind, index, offset))?;
+        }
+        if stack.len() > 1 {
+            let kind = RpnErrorKind::LeftoverOperands(stack.len());
+            return Err(RpnError::new(kind, self.count, self.len));
+        }
+        stack
+            .pop()
+            .ok_or_else(|| RpnError::new(RpnErrorKind::StackUnderflow, self.count, self.len))
+    }
+
+    // 命令を1つ実行する
+    fn step(
+        &self,
+        code: &Code<N>,
+        stack: &mut Vec<N>,
+        slots: &mut [Option<N>],
+    ) -> Result<(), RpnErrorKind> {
+        use crate::RpnErrorKind::*;
+        match code {
+            Code::Push(num) => stack.push(num.clone()),
+            Code::Stack(word) => word.apply(stack).ok_or(StackUnderflow)?,
+            Code::Apply(op) => apply(stack, op)?,
+            Code::Store(slot) => slots[*slot] = Some(stack.pop().ok_or(StackUnderflow)?),
+            Code::Fetch(slot) => {
+                let value = slots[*slot]
+                    .clone()
+                    .ok_or_else(|| UndefinedVariable(self.variables[*slot].clone()))?;
+                stack.push(value);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn program_matches_interpreter() {
+        let exp = "6.1 5.2 4.3 * + 3.4 2.5 / 1.6 * -";
+        let program = compile(exp).unwrap();
+        assert!(program.variables().is_empty());
+        assert_eq!(program.eval(&[]), rpn(exp));
+        assert_eq!(program.eval(&[]), rpn(exp));
+    }
+
+    #[test]
+    fn program_with_words_and_variables() {
+        let mut engine: RpnEngine = RpnEngine::default();
+        let program = engine
+            .compile(": sq dup * ; x @ sq y @ sq + sqrt r ! r @ r @ +")
+            .unwrap();
+        assert_eq!(program.variables(), ["x", "y", "r"]);
+        assert_eq!(program.eval(&[3.0, 4.0]), Ok(10.0));
+        assert_eq!(program.eval(&[5.0, 12.0]), Ok(26.0));
+        // 定義は評価器に登録される
+        assert_eq!(engine.eval("3 sq"), Ok(9.0));
+    }
+
+    #[test]
+    fn program_errors() {
+        let program = compile("1 x @ : f swap ; f").unwrap();
+        assert_eq!(
+            program.eval(&[]),
+            Err(RpnError::new(
+                RpnErrorKind::UndefinedVariable("x".to_string()),
+                1,
+                2
+            ))
+        );
+        assert_eq!(
+            program.eval(&[2.0]),
+            Err(RpnError::new(RpnErrorKind::LeftoverOperands(2), 8, 18))
+        );
+
+        let program = compile("1 : f drop drop ; f").unwrap();
+        assert_eq!(
+            program.eval(&[]),
+            Err(RpnError::new(RpnErrorKind::StackUnderflow, 6, 18))
+        );
+    }
+}
@@ -1,65 +1,378 @@
-use num_cpus;
-
-use bitonic_sorter::fourth::sort as par_sort;
-use bitonic_sorter::third::sort as seq_sort;
-use bitonic_sorter::utils::{is_sorted_ascending, new_u32_vec};
-use bitonic_sorter::SortOrder;
+use bitonic_sorter::float::{radix_sort_floats, NanPolicy};
+use bitonic_sorter::radix;
+use bitonic_sorter::simd::{self, SimdKey};
+use bitonic_sorter::utils::{new_u32_vec_with, Distribution};
+use bitonic_sorter::{fourth, third, SortOrder};
+use rayon::slice::ParallelSliceMut;
 
+use std::cmp::Ordering;
+use std::env;
 use std::str::FromStr;
 use std::time::Instant;
-use std::{env, f64};
 
-fn main() {
-    if let Some(n) = env::args().nth(1) {
-        let bits = u32::from_str(&n).expect("error parsing argument");
-        run_sorts(bits);
-    } else {
-        eprintln!(
-            "Usage {} <number of elements in bits>",
-            env::args().nth(0).unwrap()
-        );
-        std::process::exit(1);
+const SORTERS: [&str; 6] = ["seq", "par", "simd", "radix", "std", "rayon"];
+const TYPES: [&str; 4] = ["u32", "i32", "u64", "f32"];
+
+fn usage() -> ! {
+    eprintln!(
+        "Usage: {} [OPTIONS] [BITS]
+
+Options:
+  -n, --bits LIST     number of elements in bits, e.g. 16,20 (default: 20)
+  -d, --dist LIST     {} (default: uniform)
+  -t, --type LIST     {} (default: u32)
+  -s, --sorter LIST   {} (default: all)
+  -i, --iters N       iterations per case (default: 5)
+  -f, --format FMT    text|json|csv (default: text)
+
+BITS must be less than {}. Speedups are the median time of seq and std
+divided by the median time of each sorter.",
+        env::args().next().unwrap(),
+        Distribution::ALL.map(|d| d.name()).join(","),
+        TYPES.join(","),
+        SORTERS.join(","),
+        usize::BITS,
+    );
+    std::process::exit(1);
+}
+
+struct Options {
+    bits: Vec<u32>,
+    dists: Vec<Distribution>,
+    types: Vec<String>,
+    sorters: Vec<String>,
+    iters: usize,
+    format: String,
+}
+
+impl Options {
+    fn parse() -> Self {
+        let mut opts = Options {
+            bits: vec![20],
+            dists: vec![Distribution::Uniform],
+            types: vec![String::from("u32")],
+            sorters: SORTERS.iter().map(|s| s.to_string()).collect(),
+            iters: 5,
+            format: String::from("text"),
+        };
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().unwrap_or_else(|| usage());
+            match arg.as_str() {
+                "-n" | "--bits" => opts.bits = parse_list(&value()),
+                "-d" | "--dist" => opts.dists = parse_list(&value()),
+                "-t" | "--type" => opts.types = parse_names(&value(), &TYPES),
+                "-s" | "--sorter" => opts.sorters = parse_names(&value(), &SORTERS),
+                "-i" | "--iters" => opts.iters = parse_value(&value()),
+                "-f" | "--format" => {
+                    opts.format = parse_names(&value(), &["text", "json", "csv"]).remove(0)
+                }
+                // 以前と同じく、要素数のビット数だけを渡すこともできる
+                _ if !arg.starts_with('-') => opts.bits = parse_list(&arg),
+                _ => usage(),
+            }
+        }
+        if opts.iters == 0 || opts.bits.iter().any(|&bits| bits >= usize::BITS) {
+            usage();
+        }
+        opts
+    }
+}
+
+fn parse_value<T: FromStr>(s: &str) -> T {
+    s.trim().parse().unwrap_or_else(|_| usage())
+}
+
+fn parse_list<T: FromStr>(s: &str) -> Vec<T> {
+    s.split(',').map(parse_value).collect()
+}
+
+fn parse_names(s: &str, allowed: &[&str]) -> Vec<String> {
+    let names: Vec<String> = parse_list(s);
+    if names.iter().any(|n| !allowed.contains(&n.as_str())) {
+        usage();
+    }
+    names
+}
+
+/// ベンチマークで扱う要素の型
+trait Key: SimdKey + 'static {
+    /// 順序を保って u32 から変換する
+    fn from_u32(v: u32) -> Self;
+    fn compare(a: &Self, b: &Self) -> Ordering;
+    /// 基数ソートで昇順に整列する
+    fn radix_sort(x: &mut [Self]);
+}
+
+impl Key for u32 {
+    fn from_u32(v: u32) -> Self {
+        v
+    }
+
+    fn compare(a: &Self, b: &Self) -> Ordering {
+        a.cmp(b)
+    }
+
+    fn radix_sort(x: &mut [Self]) {
+        radix::sort(x, &SortOrder::Ascending)
+    }
+}
+
+impl Key for i32 {
+    fn from_u32(v: u32) -> Self {
+        (v ^ 1 << 31) as i32
+    }
+
+    fn compare(a: &Self, b: &Self) -> Ordering {
+        a.cmp(b)
+    }
+
+    fn radix_sort(x: &mut [Self]) {
+        radix::sort(x, &SortOrder::Ascending)
+    }
+}
+
+impl Key for u64 {
+    fn from_u32(v: u32) -> Self {
+        (v as u64) << 32 | v.rotate_left(7) as u64
+    }
+
+    fn compare(a: &Self, b: &Self) -> Ordering {
+        a.cmp(b)
+    }
+
+    fn radix_sort(x: &mut [Self]) {
+        radix::sort(x, &SortOrder::Ascending)
     }
 }
 
-fn run_sorts(bits: u32) {
-    let len = 2.0_f64.powi(bits as i32) as usize;
+impl Key for f32 {
+    fn from_u32(v: u32) -> Self {
+        v as f32
+    }
+
+    fn compare(a: &Self, b: &Self) -> Ordering {
+        a.total_cmp(b)
+    }
+
+    fn radix_sort(x: &mut [Self]) {
+        radix_sort_floats(x, &SortOrder::Ascending, NanPolicy::Error).unwrap()
+    }
+}
+
+fn sorter<T: Key>(name: &str) -> fn(&mut [T]) {
+    match name {
+        "seq" => |x| third::sort_by(x, &T::compare),
+        "par" => |x| fourth::sort_by(x, &T::compare),
+        "simd" => |x| simd::par_sort(x, &SortOrder::Ascending),
+        "radix" => T::radix_sort,
+        "std" => |x| x.sort_unstable_by(T::compare),
+        "rayon" => |x| x.par_sort_by(T::compare),
+        _ => unreachable!(),
+    }
+}
+
+/// 1つの組み合わせの測定結果。時間の単位は秒
+struct Record {
+    sorter: String,
+    ty: String,
+    dist: Distribution,
+    len: usize,
+    iters: usize,
+    min: f64,
+    median: f64,
+    mean: f64,
+    stddev: f64,
+    /// seq と std の median をこの median で割った値。その比較対象を測っていないか、
+    /// median が 0 なら None
+    speedup_vs_seq: Option<f64>,
+    speedup_vs_std: Option<f64>,
+}
+
+impl Record {
+    fn new(sorter: &str, ty: &str, dist: Distribution, len: usize, mut times: Vec<f64>) -> Self {
+        times.sort_by(|a, b| a.total_cmp(b));
+        let n = times.len();
+        let median = if n % 2 == 1 {
+            times[n / 2]
+        } else {
+            (times[n / 2 - 1] + times[n / 2]) / 2.0
+        };
+        let mean = times.iter().sum::<f64>() / n as f64;
+        let variance = times.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / n as f64;
+        Record {
+            sorter: sorter.to_string(),
+            ty: ty.to_string(),
+            dist,
+            len,
+            iters: n,
+            min: times[0],
+            median,
+            mean,
+            stddev: variance.sqrt(),
+            speedup_vs_seq: None,
+            speedup_vs_std: None,
+        }
+    }
+}
+
+fn run_case<T: Key>(opts: &Options, ty: &str, dist: Distribution, len: usize) -> Vec<Record> {
+    let input: Vec<T> = new_u32_vec_with(len, dist)
+        .into_iter()
+        .map(T::from_u32)
+        .collect();
+    let mut records: Vec<Record> = opts
+        .sorters
+        .iter()
+        .map(|name| {
+            let sort = sorter::<T>(name);
+            let times = (0..opts.iters)
+                .map(|_| {
+                    let mut x = input.clone();
+                    let start = Instant::now();
+                    sort(&mut x);
+                    let secs = start.elapsed().as_secs_f64();
+                    assert!(
+                        x.windows(2)
+                            .all(|p| T::compare(&p[0], &p[1]) != Ordering::Greater),
+                        "{} did not sort {} {}",
+                        name,
+                        ty,
+                        dist.name()
+                    );
+                    secs
+                })
+                .collect();
+            Record::new(name, ty, dist, len, times)
+        })
+        .collect();
+
+    let median_of = |name| {
+        records
+            .iter()
+            .find(|r| r.sorter == name && r.median > 0.0)
+            .map(|r| r.median)
+    };
+    let (seq, std) = (median_of("seq"), median_of("std"));
+    for r in &mut records {
+        // 速すぎて median が 0 になったときは、比を求められない
+        let speedup = |base: Option<f64>| base.filter(|_| r.median > 0.0).map(|b| b / r.median);
+        r.speedup_vs_seq = speedup(seq);
+        r.speedup_vs_std = speedup(std);
+        if opts.format == "text" {
+            print_text(r);
+        }
+    }
+    records
+}
+
+// 速度比を書式化する。測っていないか有限の値でなければ none を返す
+fn format_speedup(speedup: Option<f64>, none: &str) -> String {
+    match speedup {
+        Some(s) if s.is_finite() => format!("{:.3}", s),
+        _ => none.to_string(),
+    }
+}
+
+fn print_text(r: &Record) {
     println!(
-        "sorting {} integers ({:.1} MB",
-        len,
-        (len * std::mem::size_of::<u32>()) as f64 / 1024.0 / 1024.0
+        "{:<6} {:<4} {:<10} {:>10}  min {:.6}s  median {:.6}s  stddev {:.6}s  \
+         vs seq {:>7}x  vs std {:>7}x",
+        r.sorter,
+        r.ty,
+        r.dist.name(),
+        r.len,
+        r.min,
+        r.median,
+        r.stddev,
+        format_speedup(r.speedup_vs_seq, "-"),
+        format_speedup(r.speedup_vs_std, "-")
     );
+}
 
+fn print_csv(records: &[Record]) {
     println!(
-        "cpu info: {} physical cores, {} logical cores",
-        num_cpus::get_physical(),
-        num_cpus::get()
+        "sorter,type,distribution,len,iters,min,median,mean,stddev,speedup_vs_seq,speedup_vs_std"
     );
-
-    let seq_duration = time_sort(&seq_sort, len, "seq_sort");
-    let par_duration = time_sort(&par_sort, len, "par_sort");
-
-    println!("speed up: {:.2}x", seq_duration / par_duration);
+    for r in records {
+        println!(
+            "{},{},{},{},{},{:.9},{:.9},{:.9},{:.9},{},{}",
+            r.sorter,
+            r.ty,
+            r.dist.name(),
+            r.len,
+            r.iters,
+            r.min,
+            r.median,
+            r.mean,
+            r.stddev,
+            format_speedup(r.speedup_vs_seq, ""),
+            format_speedup(r.speedup_vs_std, "")
+        );
+    }
 }
 
-fn time_sort<F>(sorter: &F, len: usize, name: &str) -> f64
-where
-    F: Fn(&mut [u32], &SortOrder) -> Result<(), String>,
-{
-    let mut x = new_u32_vec(len);
-    let start = Instant::now();
-    sorter(&mut x, &SortOrder::Ascending).expect("Failed to sort: ");
-    let dur = start.elapsed();
-
-    let nano_secs = dur.subsec_nanos() as f64 + dur.as_secs() as f64 * 1e9_f64;
+// 値はすべて数値か、引用符やエスケープを含まない名前なので、そのまま書き出せる
+fn print_json(records: &[Record]) {
+    println!("{{");
     println!(
-        "{}: sorted {} integers in {} seconds",
-        name,
-        len,
-        nano_secs / 1e9
+        "  \"cpu\": {{\"physical_cores\": {}, \"logical_cores\": {}}},",
+        num_cpus::get_physical(),
+        num_cpus::get()
     );
+    println!("  \"results\": [");
+    for (i, r) in records.iter().enumerate() {
+        println!(
+            "    {{\"sorter\": \"{}\", \"type\": \"{}\", \"distribution\": \"{}\", \"len\": {}, \"iters\": {}, \
+             \"min\": {:.9}, \"median\": {:.9}, \"mean\": {:.9}, \"stddev\": {:.9}, \
+             \"speedup_vs_seq\": {}, \"speedup_vs_std\": {}}}{}",
+            r.sorter,
+            r.ty,
+            r.dist.name(),
+            r.len,
+            r.iters,
+            r.min,
+            r.median,
+            r.mean,
+            r.stddev,
+            format_speedup(r.speedup_vs_seq, "null"),
+            format_speedup(r.speedup_vs_std, "null"),
+            if i + 1 < records.len() { "," } else { "" }
+        );
+    }
+    println!("  ]");
+    println!("}}");
+}
+
+fn main() {
+    let opts = Options::parse();
+    if opts.format == "text" {
+        println!(
+            "cpu info: {} physical cores, {} logical cores",
+            num_cpus::get_physical(),
+            num_cpus::get()
+        );
+    }
 
-    assert!(is_sorted_ascending(&x));
+    let mut records = Vec::new();
+    for &bits in &opts.bits {
+        let len = 1usize << bits;
+        for ty in &opts.types {
+            for &dist in &opts.dists {
+                records.extend(match ty.as_str() {
+                    "u32" => run_case::<u32>(&opts, ty, dist, len),
+                    "i32" => run_case::<i32>(&opts, ty, dist, len),
+                    "u64" => run_case::<u64>(&opts, ty, dist, len),
+                    "f32" => run_case::<f32>(&opts, ty, dist, len),
+                    _ => unreachable!(),
+                });
+            }
+        }
+    }
 
-    nano_secs
+    match opts.format.as_str() {
+        "json" => print_json(&records),
+        "csv" => print_csv(&records),
+        _ => {}
+    }
 }
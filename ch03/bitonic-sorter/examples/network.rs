@@ -0,0 +1,28 @@
+use bitonic_sorter::network::Network;
+
+use std::env;
+use std::str::FromStr;
+
+// 長さ n のバイトニックネットワークを DOT か SVG で標準出力に書き出す
+fn main() {
+    let mut args = env::args().skip(1);
+    let n = args.next().and_then(|n| usize::from_str(&n).ok());
+    let format = args.next().unwrap_or_else(|| String::from("svg"));
+    let network = match n {
+        Some(n) => Network::bitonic(n),
+        None => usage(),
+    };
+    match format.as_str() {
+        "dot" => print!("{}", network.to_dot()),
+        "svg" => print!("{}", network.to_svg()),
+        _ => usage(),
+    }
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "Usage: {} <number of elements> [dot|svg]",
+        env::args().next().unwrap()
+    );
+    std::process::exit(1);
+}
@@ -0,0 +1,73 @@
+use bitonic_sorter::fourth::sort as par_sort;
+use bitonic_sorter::select::{partial_sort, select_nth, top_k};
+use bitonic_sorter::utils::new_u32_vec;
+use bitonic_sorter::SortOrder;
+
+use std::env;
+use std::str::FromStr;
+use std::time::Instant;
+
+// 上位 k 個と中央値を、全体を整列する場合と比べる
+fn main() {
+    let mut args = env::args().skip(1);
+    let bits = args.next().and_then(|n| u32::from_str(&n).ok());
+    let k = args
+        .next()
+        .map(|k| usize::from_str(&k).expect("error parsing k"))
+        .unwrap_or(100);
+    let len = match bits {
+        Some(bits) => 1usize << bits,
+        None => {
+            eprintln!(
+                "Usage: {} <number of elements in bits> [k]",
+                env::args().next().unwrap()
+            );
+            std::process::exit(1);
+        }
+    };
+    let x = new_u32_vec(len);
+    println!("len: {}, k: {}", len, k);
+
+    let full = time("par_sort (full)", || {
+        let mut y = x.clone();
+        par_sort(&mut y, &SortOrder::Ascending);
+        y.truncate(k);
+        y
+    });
+    let top = time("top_k", || top_k(&x, k, &SortOrder::Ascending));
+    let partial = time("partial_sort", || {
+        let mut y = x.clone();
+        partial_sort(&mut y, k, &SortOrder::Ascending);
+        y.truncate(k);
+        y
+    });
+    assert_eq!(top.0, full.0);
+    assert_eq!(partial.0, full.0);
+    println!("top_k speed up: {:.2}x", full.1 / top.1);
+    println!("partial_sort speed up: {:.2}x", full.1 / partial.1);
+
+    let full_median = time("par_sort (full, median)", || {
+        let mut y = x.clone();
+        par_sort(&mut y, &SortOrder::Ascending);
+        y[len / 2]
+    });
+    let median = time("select_nth (median)", || {
+        let mut y = x.clone();
+        *select_nth(&mut y, len / 2, &SortOrder::Ascending)
+    });
+    let std_median = time("std select_nth_unstable (median)", || {
+        let mut y = x.clone();
+        *y.select_nth_unstable(len / 2).1
+    });
+    assert_eq!(median.0, full_median.0);
+    assert_eq!(median.0, std_median.0);
+    println!("select_nth speed up: {:.2}x", full_median.1 / median.1);
+}
+
+fn time<T, F: FnOnce() -> T>(name: &str, f: F) -> (T, f64) {
+    let start = Instant::now();
+    let result = f();
+    let secs = start.elapsed().as_secs_f64();
+    println!("{}: {} seconds", name, secs);
+    (result, secs)
+}
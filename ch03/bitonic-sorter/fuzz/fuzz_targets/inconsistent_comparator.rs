@@ -0,0 +1,53 @@
+#![no_main]
+
+use bitonic_sorter::{fourth, select, third};
+use libfuzzer_sys::fuzz_target;
+use std::cmp::Ordering;
+use std::sync::atomic::{self, AtomicUsize};
+
+// 全順序にならない比較関数を渡しても、パニックせず要素も失われないことを確かめる
+fuzz_target!(|input: (Vec<u16>, Vec<u8>, usize)| {
+    let (x, answers, n) = input;
+    if answers.is_empty() {
+        return;
+    }
+    // 呼ばれるたびに answers の次のバイトで結果を決める
+    let calls = AtomicUsize::new(0);
+    let comparator = |_: &u16, _: &u16| {
+        let i = calls.fetch_add(1, atomic::Ordering::Relaxed);
+        match answers[i % answers.len()] % 3 {
+            0 => Ordering::Less,
+            1 => Ordering::Equal,
+            _ => Ordering::Greater,
+        }
+    };
+    let mut expected = x.clone();
+    expected.sort();
+    let check = |mut y: Vec<u16>| {
+        y.sort();
+        assert_eq!(y, expected);
+    };
+
+    for sort in [third::sort_by, fourth::sort_by, third::stable_sort_by] {
+        let mut y = x.clone();
+        sort(&mut y, &comparator);
+        check(y);
+    }
+    let mut y = x.clone();
+    fourth::stable_sort_by(&mut y, &comparator);
+    check(y);
+
+    let (mut keys, mut values) = (x.clone(), x.clone());
+    third::sort_pairs_by(&mut keys, &mut values, &comparator);
+    assert_eq!(keys, values);
+    check(keys);
+
+    let mut y = x.clone();
+    select::partial_sort_by(&mut y, n % (x.len() + 1), &comparator);
+    check(y);
+    if !x.is_empty() {
+        let mut y = x.clone();
+        select::select_nth_by(&mut y, n % x.len(), &comparator);
+        check(y);
+    }
+});
@@ -0,0 +1,33 @@
+#![no_main]
+
+use bitonic_sorter::keys::by_key;
+use bitonic_sorter::{fourth, third};
+use libfuzzer_sys::fuzz_target;
+
+// 比較関数を受け取る経路の結果を std の sort_by と比べる
+fuzz_target!(|x: Vec<(u8, u16)>| {
+    let comparator = |a: &(u8, u16), b: &(u8, u16)| (a.0 % 16).cmp(&(b.0 % 16));
+    let mut expected = x.clone();
+    expected.sort_by(comparator);
+
+    // 安定な版は完全に一致する
+    let mut y = x.clone();
+    third::stable_sort_by(&mut y, &comparator);
+    assert_eq!(y, expected);
+    let mut y = x.clone();
+    fourth::stable_sort_by(&mut y, &comparator);
+    assert_eq!(y, expected);
+
+    // 安定でない版はキーの順だけが一致する
+    let keys = |v: &[(u8, u16)]| v.iter().map(|p| p.0 % 16).collect::<Vec<_>>();
+    let spec = by_key(|p: &(u8, u16)| p.0 % 16);
+    let mut y = x.clone();
+    third::sort_by(&mut y, &comparator);
+    assert_eq!(keys(&y), keys(&expected));
+    let mut y = x.clone();
+    fourth::sort_by(&mut y, &comparator);
+    assert_eq!(keys(&y), keys(&expected));
+    let mut y = x.clone();
+    fourth::sort_by(&mut y, &spec.comparator());
+    assert_eq!(keys(&y), keys(&expected));
+});
@@ -0,0 +1,543 @@
+use super::fourth;
+use super::SortOrder;
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+// メモリに載らない大きさの固定長レコードの列を整列する
+//
+// 1. 入力からメモリの上限まで読み込み、fourth::sort_by で整列して一時ファイルに書き出す (ラン)
+// 2. すべてのランを k-way マージして出力に書き出す
+//
+// 入力が1つのランに収まれば一時ファイルは作らない。ランは段ごとにまとめ、ある段のランが
+// max_fan_in 個になると1つのランにマージして次の段に移す。こうして同時に開く一時ファイルを
+// 段の数 × max_fan_in 個までに抑える
+
+/// 固定長レコードとバイト列の変換
+pub trait RecordCodec: Sync {
+    type Record: Send;
+
+    /// 1レコードのバイト数
+    fn record_len(&self) -> usize;
+
+    /// bytes の長さは record_len() に等しい
+    fn decode(&self, bytes: &[u8]) -> Self::Record;
+
+    /// out の長さは record_len() に等しい
+    fn encode(&self, record: &Self::Record, out: &mut [u8]);
+}
+
+/// バイト列のままのレコード。辞書順で比較される
+#[derive(Debug, Clone, Copy)]
+pub struct FixedWidth(pub usize);
+
+impl RecordCodec for FixedWidth {
+    type Record = Box<[u8]>;
+
+    fn record_len(&self) -> usize {
+        self.0
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Self::Record {
+        bytes.into()
+    }
+
+    fn encode(&self, record: &Self::Record, out: &mut [u8]) {
+        out.copy_from_slice(record);
+    }
+}
+
+/// リトルエンディアンの u32
+#[derive(Debug, Clone, Copy)]
+pub struct U32Le;
+
+impl RecordCodec for U32Le {
+    type Record = u32;
+
+    fn record_len(&self) -> usize {
+        4
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Self::Record {
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn encode(&self, record: &Self::Record, out: &mut [u8]) {
+        out.copy_from_slice(&record.to_le_bytes());
+    }
+}
+
+/// リトルエンディアンの u64
+#[derive(Debug, Clone, Copy)]
+pub struct U64Le;
+
+impl RecordCodec for U64Le {
+    type Record = u64;
+
+    fn record_len(&self) -> usize {
+        8
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Self::Record {
+        u64::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn encode(&self, record: &Self::Record, out: &mut [u8]) {
+        out.copy_from_slice(&record.to_le_bytes());
+    }
+}
+
+const DEFAULT_MEMORY_BUDGET: usize = 256 * 1024 * 1024;
+const MIN_BUFFER_SIZE: usize = 8 * 1024;
+const DEFAULT_MAX_FAN_IN: usize = 64;
+
+/// 外部ソートの設定
+///
+/// memory_budget はメモリに読み込むレコードの大きさの目安 (バイト)。
+/// max_fan_in は1回のマージで同時に開くランの数の上限。
+/// 一時ファイルは temp_dir に作り、ソートが終わると削除される
+#[derive(Debug, Clone)]
+pub struct ExternalSorter<C> {
+    codec: C,
+    memory_budget: usize,
+    max_fan_in: usize,
+    temp_dir: Option<PathBuf>,
+}
+
+impl<C: RecordCodec> ExternalSorter<C> {
+    pub fn new(codec: C) -> Self {
+        ExternalSorter {
+            codec,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            max_fan_in: DEFAULT_MAX_FAN_IN,
+            temp_dir: None,
+        }
+    }
+
+    pub fn with_memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = bytes;
+        self
+    }
+
+    /// 2 より小さい値は 2 として扱う
+    pub fn with_max_fan_in(mut self, runs: usize) -> Self {
+        self.max_fan_in = runs.max(2);
+        self
+    }
+
+    pub fn with_temp_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.temp_dir = Some(dir.into());
+        self
+    }
+
+    pub fn sort<R, W>(&self, input: R, output: W, order: &SortOrder) -> io::Result<()>
+    where
+        R: Read,
+        W: Write,
+        C::Record: Ord,
+    {
+        match *order {
+            SortOrder::Ascending => self.sort_by(input, output, &|a, b| a.cmp(b)),
+            SortOrder::Descending => self.sort_by(input, output, &|a, b| b.cmp(a)),
+        }
+    }
+
+    /// input のレコードを整列して output に書き出す
+    ///
+    /// レコード長が 0 なら InvalidInput、入力の長さがレコード長の倍数でなければ
+    /// InvalidData のエラーになる
+    pub fn sort_by<R, W, F>(&self, input: R, output: W, comparator: &F) -> io::Result<()>
+    where
+        R: Read,
+        W: Write,
+        F: Sync + Fn(&C::Record, &C::Record) -> Ordering,
+    {
+        if self.codec.record_len() == 0 {
+            // 入力の終わりを検出できず、空のランを書き出し続けてしまう
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "record length must not be zero",
+            ));
+        }
+        let mut input = BufReader::new(input);
+        let mut output = BufWriter::new(output);
+        // levels[i] は i 回マージを経たランの列
+        let mut levels: Vec<Vec<File>> = Vec::new();
+        loop {
+            let mut chunk = self.read_chunk(&mut input)?;
+            let last = chunk.len() < self.chunk_len();
+            fourth::sort_by(&mut chunk, comparator);
+            if levels.is_empty() && last {
+                // 1つのランに収まったので、そのまま出力する
+                self.write_records(&chunk, &mut output)?;
+                return output.flush();
+            }
+            if !chunk.is_empty() {
+                let run = self.spill(&chunk)?;
+                self.push_run(&mut levels, run, comparator)?;
+            }
+            if last {
+                break;
+            }
+        }
+        let mut runs: Vec<File> = levels.into_iter().flatten().collect();
+        while runs.len() > self.max_fan_in {
+            let rest = runs.split_off(self.max_fan_in);
+            let merged = self.merge_to_temp(runs, comparator)?;
+            runs = rest;
+            runs.push(merged);
+        }
+        self.merge(runs, &mut output, comparator)?;
+        output.flush()
+    }
+
+    // 1つのランに入れるレコードの数
+    fn chunk_len(&self) -> usize {
+        let record_size = self.codec.record_len() + std::mem::size_of::<C::Record>();
+        (self.memory_budget / record_size.max(1)).max(1)
+    }
+
+    // 最大 chunk_len() 個のレコードを読む。入力の終わりに達すると短くなる
+    fn read_chunk(&self, input: &mut impl Read) -> io::Result<Vec<C::Record>> {
+        let mut chunk = Vec::new();
+        let mut buf = vec![0; self.codec.record_len()];
+        while chunk.len() < self.chunk_len() && read_record(input, &mut buf)? {
+            chunk.push(self.codec.decode(&buf));
+        }
+        Ok(chunk)
+    }
+
+    fn write_records(&self, records: &[C::Record], output: &mut impl Write) -> io::Result<()> {
+        let mut buf = vec![0; self.codec.record_len()];
+        for record in records {
+            self.codec.encode(record, &mut buf);
+            output.write_all(&buf)?;
+        }
+        Ok(())
+    }
+
+    // 整列済みのレコードを一時ファイルに書き出す
+    fn spill(&self, records: &[C::Record]) -> io::Result<File> {
+        self.write_temp(|writer| self.write_records(records, writer))
+    }
+
+    // ランをマージして一時ファイルに書き出す
+    fn merge_to_temp<F>(&self, runs: Vec<File>, comparator: &F) -> io::Result<File>
+    where
+        F: Fn(&C::Record, &C::Record) -> Ordering,
+    {
+        self.write_temp(|writer| self.merge(runs, writer, comparator))
+    }
+
+    // write で一時ファイルに書き、先頭に戻して返す
+    fn write_temp<OP>(&self, write: OP) -> io::Result<File>
+    where
+        OP: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+    {
+        let file = match self.temp_dir {
+            Some(ref dir) => tempfile::tempfile_in(dir)?,
+            None => tempfile::tempfile()?,
+        };
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(file)
+    }
+
+    // ランを最初の段に加える。段のランが max_fan_in 個になったら、マージして次の段に移す
+    fn push_run<F>(&self, levels: &mut Vec<Vec<File>>, run: File, comparator: &F) -> io::Result<()>
+    where
+        F: Fn(&C::Record, &C::Record) -> Ordering,
+    {
+        let mut run = run;
+        let mut level = 0;
+        loop {
+            if level == levels.len() {
+                levels.push(Vec::new());
+            }
+            levels[level].push(run);
+            if levels[level].len() < self.max_fan_in {
+                return Ok(());
+            }
+            run = self.merge_to_temp(std::mem::take(&mut levels[level]), comparator)?;
+            level += 1;
+        }
+    }
+
+    // すべてのランの先頭を比べ、最小のものから順に書き出す
+    fn merge<F>(&self, runs: Vec<File>, output: &mut impl Write, comparator: &F) -> io::Result<()>
+    where
+        F: Fn(&C::Record, &C::Record) -> Ordering,
+    {
+        let capacity = (self.memory_budget / (runs.len() + 1)).max(MIN_BUFFER_SIZE);
+        let mut readers: Vec<_> = runs
+            .into_iter()
+            .map(|run| BufReader::with_capacity(capacity, run))
+            .collect();
+        let mut buf = vec![0; self.codec.record_len()];
+
+        // 空のランは先頭のレコードがないので、ヒープに入れない
+        let mut heads = Vec::with_capacity(readers.len());
+        for reader in readers.iter_mut() {
+            heads.push(if read_record(reader, &mut buf)? {
+                Some(self.codec.decode(&buf))
+            } else {
+                None
+            });
+        }
+        let mut heap = RunHeap::new(heads, comparator);
+
+        while let Some(run) = heap.peek() {
+            self.codec.encode(heap.head(run), &mut buf);
+            output.write_all(&buf)?;
+            if read_record(&mut readers[run], &mut buf)? {
+                heap.replace_top(self.codec.decode(&buf));
+            } else {
+                heap.pop();
+            }
+        }
+        Ok(())
+    }
+}
+
+// 1レコードを buf に読む。レコードの境界で入力が終わっていれば false を返す
+fn read_record(input: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "input length is not a multiple of the record length",
+                ))
+            }
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+// ランの先頭のレコードを比較関数で並べる二分ヒープ
+//
+// heap にはランの番号が入り、heads[run] がそのランの先頭のレコード。
+// 読み終えたランの先頭は None で、heap には入っていない
+struct RunHeap<'a, T, F> {
+    heads: Vec<Option<T>>,
+    heap: Vec<usize>,
+    comparator: &'a F,
+}
+
+impl<'a, T, F> RunHeap<'a, T, F>
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    fn new(heads: Vec<Option<T>>, comparator: &'a F) -> Self {
+        let mut heap = RunHeap {
+            heap: (0..heads.len())
+                .filter(|&run| heads[run].is_some())
+                .collect(),
+            heads,
+            comparator,
+        };
+        for i in (0..heap.heap.len() / 2).rev() {
+            heap.sift_down(i);
+        }
+        heap
+    }
+
+    fn peek(&self) -> Option<usize> {
+        self.heap.first().copied()
+    }
+
+    // run は heap にあるランでなければならない
+    fn head(&self, run: usize) -> &T {
+        self.heads[run].as_ref().unwrap()
+    }
+
+    // 最小のランの先頭を、そのランの次のレコードに置き換える
+    fn replace_top(&mut self, record: T) {
+        self.heads[self.heap[0]] = Some(record);
+        self.sift_down(0);
+    }
+
+    // 最小のランを取り除く
+    fn pop(&mut self) {
+        let run = self.heap.swap_remove(0);
+        self.heads[run] = None;
+        self.sift_down(0);
+    }
+
+    // 等しいときはランの番号で比べ、前のランのレコードを先に出す
+    fn less(&self, a: usize, b: usize) -> bool {
+        let (ra, rb) = (self.heap[a], self.heap[b]);
+        (self.comparator)(self.head(ra), self.head(rb)).then(ra.cmp(&rb)) == Ordering::Less
+    }
+
+    fn sift_down(&mut self, mut i: usize) {
+        loop {
+            let (left, right) = (2 * i + 1, 2 * i + 2);
+            let mut smallest = i;
+            if left < self.heap.len() && self.less(left, smallest) {
+                smallest = left;
+            }
+            if right < self.heap.len() && self.less(right, smallest) {
+                smallest = right;
+            }
+            if smallest == i {
+                return;
+            }
+            self.heap.swap(i, smallest);
+            i = smallest;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExternalSorter, FixedWidth, U32Le, U64Le};
+    use crate::utils::new_u32_vec;
+    use crate::SortOrder::*;
+    use std::io::{Cursor, Write};
+
+    fn to_bytes(x: &[u32]) -> Vec<u8> {
+        x.iter().flat_map(|n| n.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn sort_u32_in_one_run() {
+        let x = new_u32_vec(1000);
+        let mut expected = x.clone();
+        expected.sort();
+        let mut output = Vec::new();
+        let sorter = ExternalSorter::new(U32Le);
+        sorter
+            .sort(Cursor::new(to_bytes(&x)), &mut output, &Ascending)
+            .unwrap();
+        assert_eq!(output, to_bytes(&expected));
+    }
+
+    #[test]
+    fn merge_skips_empty_runs() {
+        let sorter = ExternalSorter::new(U32Le);
+        let runs = [vec![], vec![3, 8], vec![], vec![1, 5, 9]]
+            .iter()
+            .map(|run: &Vec<u32>| sorter.write_temp(|w| w.write_all(&to_bytes(run))).unwrap())
+            .collect();
+        let mut output = Vec::new();
+        sorter.merge(runs, &mut output, &|a, b| a.cmp(b)).unwrap();
+        assert_eq!(output, to_bytes(&[1, 3, 5, 8, 9]));
+    }
+
+    #[test]
+    fn sort_u32_with_many_runs() {
+        for len in [0, 1, 99, 100, 101, 12345] {
+            let x = new_u32_vec(len);
+            let mut expected = x.clone();
+            expected.sort_by(|a, b| b.cmp(a));
+            let mut output = Vec::new();
+            // 1つのランに 100 / (4 + 4) = 12 レコード
+            let sorter = ExternalSorter::new(U32Le).with_memory_budget(100);
+            sorter
+                .sort(Cursor::new(to_bytes(&x)), &mut output, &Descending)
+                .unwrap();
+            assert_eq!(output, to_bytes(&expected), "len: {}", len);
+        }
+    }
+
+    #[test]
+    fn sort_fixed_width_records_by_key() {
+        // 先頭4バイトをキー、残り4バイトを元の位置とするレコード
+        let x = new_u32_vec(5000);
+        let input: Vec<u8> = x
+            .iter()
+            .enumerate()
+            .flat_map(|(i, n)| {
+                let mut record = (n % 16).to_be_bytes().to_vec();
+                record.extend_from_slice(&(i as u32).to_be_bytes());
+                record
+            })
+            .collect();
+        let mut output = Vec::new();
+        let sorter = ExternalSorter::new(FixedWidth(8))
+            .with_memory_budget(4096)
+            .with_temp_dir(std::env::temp_dir());
+        sorter
+            .sort_by(Cursor::new(&input), &mut output, &|a, b| {
+                a[..4].cmp(&b[..4])
+            })
+            .unwrap();
+
+        // 安定ではないので、キーの順とレコードの集合だけを確かめる
+        let mut records: Vec<&[u8]> = output.chunks(8).collect();
+        assert!(records.windows(2).all(|w| w[0][..4] <= w[1][..4]));
+        let mut expected: Vec<&[u8]> = input.chunks(8).collect();
+        records.sort();
+        expected.sort();
+        assert_eq!(records, expected);
+    }
+
+    #[test]
+    fn sort_u64_records() {
+        let x: Vec<u64> = new_u32_vec(777)
+            .into_iter()
+            .map(|n| (n as u64) << 20)
+            .collect();
+        let mut expected = x.clone();
+        expected.sort();
+        let input: Vec<u8> = x.iter().flat_map(|n| n.to_le_bytes()).collect();
+        let mut output = Vec::new();
+        let sorter = ExternalSorter::new(U64Le).with_memory_budget(1000);
+        sorter
+            .sort(Cursor::new(input), &mut output, &Ascending)
+            .unwrap();
+        let sorted: Vec<u64> = output
+            .chunks(8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(sorted, expected);
+    }
+
+    #[test]
+    fn sort_with_bounded_fan_in() {
+        let x = new_u32_vec(20000);
+        let mut expected = x.clone();
+        expected.sort();
+        // 1つのランに 80 / 8 = 10 レコードで、2000 個のランができる
+        for fan_in in [0, 2, 3, 7, 64] {
+            let mut output = Vec::new();
+            let sorter = ExternalSorter::new(U32Le)
+                .with_memory_budget(80)
+                .with_max_fan_in(fan_in);
+            sorter
+                .sort(Cursor::new(to_bytes(&x)), &mut output, &Ascending)
+                .unwrap();
+            assert_eq!(output, to_bytes(&expected), "fan in: {}", fan_in);
+        }
+    }
+
+    #[test]
+    fn sort_zero_length_records_fails() {
+        let mut output = Vec::new();
+        let sorter = ExternalSorter::new(FixedWidth(0));
+        let err = sorter
+            .sort(Cursor::new(vec![1, 2, 3]), &mut output, &Ascending)
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn sort_truncated_input_fails() {
+        let mut output = Vec::new();
+        let sorter = ExternalSorter::new(U32Le);
+        let err = sorter
+            .sort(Cursor::new(vec![1, 2, 3, 4, 5]), &mut output, &Ascending)
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
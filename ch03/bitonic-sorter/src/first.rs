@@ -1,24 +1,25 @@
 pub fn sort(x: &mut [u32], up: bool) {
     if x.len() > 1 {
+        // 前半を逆向き、後半を指定の向きに並べると、長さによらずバイトニック列になる
         let mid_point = x.len() / 2;
-        sort(&mut x[..mid_point], true);
-        sort(&mut x[mid_point..], false);
+        sort(&mut x[..mid_point], !up);
+        sort(&mut x[mid_point..], up);
         sub_sort(x, up);
     }
 }
 
 fn sub_sort(x: &mut [u32], up: bool) {
     if x.len() > 1 {
-        compare_and_swap(x, up);
-        let mid_point = x.len() / 2;
+        // x.len() 未満で最大の2のべき乗で区切る。長さが2のべき乗なら半分になる
+        let mid_point = x.len().next_power_of_two() / 2;
+        compare_and_swap(x, mid_point, up);
         sub_sort(&mut x[..mid_point], up);
         sub_sort(&mut x[mid_point..], up);
     }
 }
 
-fn compare_and_swap(x: &mut [u32], up: bool) {
-    let mid_point = x.len() / 2;
-    for i in 0..mid_point {
+fn compare_and_swap(x: &mut [u32], mid_point: usize, up: bool) {
+    for i in 0..x.len() - mid_point {
         if (x[i] > x[mid_point + i]) == up {
             x.swap(i, mid_point + i);
         }
@@ -28,6 +29,7 @@ fn compare_and_swap(x: &mut [u32], up: bool) {
 #[cfg(test)]
 mod tests {
     use super::sort;
+    use crate::utils::new_u32_vec;
 
     #[test]
     fn sort_u32_ascending() {
@@ -42,4 +44,18 @@ mod tests {
         sort(&mut x, false);
         assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
     }
+
+    #[test]
+    fn sort_u32_arbitrary_length() {
+        for len in 0..=130 {
+            let mut x = new_u32_vec(len);
+            let mut expected = x.clone();
+            expected.sort();
+            sort(&mut x, true);
+            assert_eq!(x, expected);
+            sort(&mut x, false);
+            expected.reverse();
+            assert_eq!(x, expected);
+        }
+    }
 }
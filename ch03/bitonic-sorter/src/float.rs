@@ -0,0 +1,200 @@
+use super::radix::{self, RadixKey};
+use super::SortOrder;
+use std::error::Error;
+use std::fmt;
+
+// f32/f64 を IEEE 754 の全順序で整列するための共通部分
+//
+// NaN を列の端に寄せてから、残りの数をその場で符号なし整数の鍵に変換する。
+// 鍵は元の値の全順序と同じ順に並ぶので (-0.0 は 0.0 より小さい)、整数として整列してから
+// 元に戻せばよい。third と fourth の sort_floats は鍵をバイトニックソートで、
+// radix_sort_floats は radix の基数ソートで整列する
+
+/// NaN の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NanPolicy {
+    /// 整列の向きによらず先頭に置く
+    First,
+    /// 整列の向きによらず末尾に置く
+    Last,
+    /// NaN があればエラーにする
+    Error,
+}
+
+/// NanPolicy::Error のときに NaN が見つかった
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NanError {
+    /// 最初の NaN の位置
+    pub index: usize,
+}
+
+impl fmt::Display for NanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NaN at index {}", self.index)
+    }
+}
+
+impl Error for NanError {}
+
+/// sort_floats で扱える浮動小数点数の型
+pub trait Float: Copy + Send + Sync {
+    #[doc(hidden)]
+    type Key: RadixKey;
+
+    #[doc(hidden)]
+    fn is_nan(self) -> bool;
+
+    /// x のビット列をその場で鍵に変換し、鍵の列として返す
+    #[doc(hidden)]
+    fn to_keys(x: &mut [Self]) -> &mut [Self::Key];
+
+    /// to_keys で変換した鍵を、その場で元の値に戻す
+    #[doc(hidden)]
+    fn from_keys(keys: &mut [Self::Key]);
+}
+
+macro_rules! impl_float {
+    ($($t:ty => $k:ty),*) => {$(
+        impl Float for $t {
+            type Key = $k;
+
+            fn is_nan(self) -> bool {
+                <$t>::is_nan(self)
+            }
+
+            fn to_keys(x: &mut [Self]) -> &mut [Self::Key] {
+                // SAFETY: 浮動小数点数と同じ幅の符号なし整数は大きさもアラインメントも同じで、
+                // どんなビット列も有効な値になる
+                let keys: &mut [$k] =
+                    unsafe { std::slice::from_raw_parts_mut(x.as_mut_ptr().cast(), x.len()) };
+                // 負の数はすべてのビットを、正の数は符号ビットだけを反転する
+                const SIGN: $k = 1 << (<$k>::BITS - 1);
+                keys.iter_mut().for_each(|k| {
+                    *k = if *k & SIGN != 0 { !*k } else { *k | SIGN };
+                });
+                keys
+            }
+
+            fn from_keys(keys: &mut [Self::Key]) {
+                const SIGN: $k = 1 << (<$k>::BITS - 1);
+                keys.iter_mut().for_each(|k| {
+                    *k = if *k & SIGN != 0 { *k & !SIGN } else { !*k };
+                });
+            }
+        }
+    )*};
+}
+
+impl_float!(f32 => u32, f64 => u64);
+
+/// 基数ソートで x を整列する。NaN は nan に従って扱う
+///
+/// エラーのときは x を変更しない
+pub fn radix_sort_floats<T: Float>(
+    x: &mut [T],
+    order: &SortOrder,
+    nan: NanPolicy,
+) -> Result<(), NanError> {
+    sort_keys_with(x, nan, |keys| radix::sort(keys, order))
+}
+
+// NaN を端に寄せ (順序は保たない)、残りの数を鍵に変換して sort_keys で整列してから元に戻す
+pub(crate) fn sort_keys_with<T, F>(
+    x: &mut [T],
+    nan: NanPolicy,
+    sort_keys: F,
+) -> Result<(), NanError>
+where
+    T: Float,
+    F: FnOnce(&mut [T::Key]),
+{
+    let numbers = match nan {
+        NanPolicy::Error => {
+            if let Some(index) = x.iter().position(|v| v.is_nan()) {
+                return Err(NanError { index });
+            }
+            x
+        }
+        NanPolicy::First => {
+            let mut start = 0;
+            for i in 0..x.len() {
+                if x[i].is_nan() {
+                    x.swap(start, i);
+                    start += 1;
+                }
+            }
+            &mut x[start..]
+        }
+        NanPolicy::Last => {
+            let mut end = x.len();
+            for i in (0..x.len()).rev() {
+                if x[i].is_nan() {
+                    end -= 1;
+                    x.swap(i, end);
+                }
+            }
+            &mut x[..end]
+        }
+    };
+    let keys = T::to_keys(numbers);
+    sort_keys(keys);
+    T::from_keys(keys);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{radix_sort_floats, NanError, NanPolicy};
+    use crate::utils::new_u32_vec;
+    use crate::SortOrder::*;
+
+    fn bits(x: &[f64]) -> Vec<u64> {
+        x.iter().map(|v| v.to_bits()).collect()
+    }
+
+    #[test]
+    fn radix_sort_f64_in_total_order() {
+        let mut x: Vec<f64> = new_u32_vec(10000)
+            .into_iter()
+            .map(|v| (v as i32) as f64 / 3.0)
+            .chain([
+                0.0,
+                -0.0,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::MIN_POSITIVE,
+            ])
+            .collect();
+        let mut expected = x.clone();
+        expected.sort_by(|a, b| a.total_cmp(b));
+        radix_sort_floats(&mut x, &Ascending, NanPolicy::Error).unwrap();
+        assert_eq!(bits(&x), bits(&expected));
+
+        expected.reverse();
+        radix_sort_floats(&mut x, &Descending, NanPolicy::Error).unwrap();
+        assert_eq!(bits(&x), bits(&expected));
+    }
+
+    #[test]
+    fn radix_sort_f32_with_nan_policy() {
+        let x = vec![1.5f32, f32::NAN, -0.0, -f32::NAN, 0.0, -2.5];
+        let mut y = x.clone();
+        radix_sort_floats(&mut y, &Descending, NanPolicy::Last).unwrap();
+        assert_eq!(y[..4], [1.5, 0.0, -0.0, -2.5]);
+        assert!(y[2].is_sign_negative());
+        assert!(y[4..].iter().all(|v| v.is_nan()));
+
+        let mut y = x.clone();
+        radix_sort_floats(&mut y, &Ascending, NanPolicy::First).unwrap();
+        assert!(y[..2].iter().all(|v| v.is_nan()));
+        assert_eq!(y[2..], [-2.5, -0.0, 0.0, 1.5]);
+
+        let mut y = x.clone();
+        let err = radix_sort_floats(&mut y, &Ascending, NanPolicy::Error);
+        assert_eq!(err, Err(NanError { index: 1 }));
+        assert_eq!(err.unwrap_err().to_string(), "NaN at index 1");
+        // エラーのときは変更しない
+        let bits = |v: &[f32]| v.iter().map(|f| f.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&y), bits(&x));
+    }
+}
@@ -1,74 +1,401 @@
+use super::float::{self, Float, NanError, NanPolicy};
+use super::utils::apply_sorted_indices;
+pub use super::utils::{apply_permutation, invert_permutation};
 use super::SortOrder;
-use rayon;
+use rayon::prelude::*;
+use rayon::ThreadPool;
 use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::Arc;
+use std::time::Instant;
 
-pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), String>
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F)
 where
     T: Send,
     F: Sync + Fn(&T, &T) -> Ordering,
 {
-    if x.len().is_power_of_two() {
-        do_sort(x, true, comparator);
-        Ok(())
-    } else {
-        Err(format!(
-            "The length of x is not a power of two. (x.len(): {}",
-            x.len()
-        ))
+    do_sort(x, true, comparator, PARALLEL_THRESHOLD);
+}
+
+/// 並列ソートの設定
+///
+/// 分割した列がこの長さ以上なら並列に処理する閾値と、処理を動かすスレッドプールを持つ。
+/// スレッドプールを指定しなければ rayon のグローバルなプールで動く
+#[derive(Debug, Clone)]
+pub struct SortConfig {
+    threshold: usize,
+    pool: Option<Arc<ThreadPool>>,
+}
+
+impl Default for SortConfig {
+    fn default() -> Self {
+        SortConfig {
+            threshold: PARALLEL_THRESHOLD,
+            pool: None,
+        }
+    }
+}
+
+impl SortConfig {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold.max(1);
+        self
+    }
+
+    pub fn with_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// 簡単な計測から閾値を決める
+    ///
+    /// rayon::join 1回の手間と、1要素あたりの比較交換の手間を測り、
+    /// 1つのタスクの仕事が join の手間より十分大きくなる長さを閾値にする。
+    /// スレッドが1つしかなければ並列化しない
+    pub fn calibrated(mut self) -> Self {
+        let threads = match self.pool {
+            Some(ref pool) => pool.current_num_threads(),
+            None => rayon::current_num_threads(),
+        };
+        if threads <= 1 {
+            self.threshold = usize::MAX;
+            return self;
+        }
+        let join_nanos = self.run(measure_join);
+        let element_nanos = measure_compare_and_swap();
+        let threshold = (JOIN_COST_FACTOR * join_nanos / element_nanos) as usize;
+        self.threshold = threshold
+            .next_power_of_two()
+            .clamp(MIN_CALIBRATED_THRESHOLD, MAX_CALIBRATED_THRESHOLD);
+        self
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    // スレッドプールが指定されていればその中で op を実行する
+    fn run<R, OP>(&self, op: OP) -> R
+    where
+        R: Send,
+        OP: Send + FnOnce() -> R,
+    {
+        match self.pool {
+            Some(ref pool) => pool.install(op),
+            None => op(),
+        }
     }
 }
 
-pub fn sort<T: Ord + Send>(x: &mut [T], order: &SortOrder) -> Result<(), String> {
+pub fn sort_by_with_config<T, F>(x: &mut [T], comparator: &F, config: &SortConfig)
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    config.run(|| do_sort(x, true, comparator, config.threshold));
+}
+
+pub fn sort_with_config<T: Ord + Send>(x: &mut [T], order: &SortOrder, config: &SortConfig) {
+    match *order {
+        SortOrder::Ascending => sort_by_with_config(x, &|a, b| a.cmp(b), config),
+        SortOrder::Descending => sort_by_with_config(x, &|a, b| b.cmp(a), config),
+    }
+}
+
+pub fn sort<T: Ord + Send>(x: &mut [T], order: &SortOrder) {
     match *order {
         SortOrder::Ascending => sort_by(x, &|a, b| a.cmp(b)),
         SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
     }
 }
 
-const PARALLEL_THRESHOLD: usize = 4096;
+/// 整列の中断と進み具合の確認
+///
+/// 別のスレッドから cancel を呼ぶと、sort_with_control は途中で Cancelled を返す。
+///
+/// 段 (stage) はバイトニックソートの分割の1つ1つで行うマージ (2つの整列済みの列を
+/// 1つのバイトニック列として整列すること) で、長さ len の列では全部で len - 1 回ある。
+/// finished_stages は終わった段の数で、最後には total_stages(x.len()) になる。
+/// スレッドの間で数え上げが競合しないように、1024 要素未満の部分列の段は
+/// その部分列を整列し終えてからまとめて数える
+#[derive(Debug, Default)]
+pub struct SortControl {
+    cancelled: AtomicBool,
+    finished_stages: AtomicUsize,
+}
+
+impl SortControl {
+    pub fn new() -> Self {
+        Default::default()
+    }
 
-fn do_sort<T, F>(x: &mut [T], forward: bool, comparetor: &F)
+    pub fn cancel(&self) {
+        self.cancelled.store(true, AtomicOrdering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(AtomicOrdering::Relaxed)
+    }
+
+    pub fn finished_stages(&self) -> usize {
+        self.finished_stages.load(AtomicOrdering::Relaxed)
+    }
+
+    /// 長さ len の列を整列するときの段の数
+    pub fn total_stages(len: usize) -> usize {
+        len.saturating_sub(1)
+    }
+
+    fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// 整列が SortControl::cancel で中断された
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "sort cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+/// 中断できる sort_by。中断したときも x は元の要素の並べ替えになっている
+pub fn sort_by_with_control<T, F>(
+    x: &mut [T],
+    comparator: &F,
+    ctl: &SortControl,
+) -> Result<(), Cancelled>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    do_sort_with_control(x, true, comparator, PARALLEL_THRESHOLD, ctl)
+}
+
+pub fn sort_with_control<T: Ord + Send>(
+    x: &mut [T],
+    order: &SortOrder,
+    ctl: &SortControl,
+) -> Result<(), Cancelled> {
+    match *order {
+        SortOrder::Ascending => sort_by_with_control(x, &|a, b| a.cmp(b), ctl),
+        SortOrder::Descending => sort_by_with_control(x, &|a, b| b.cmp(a), ctl),
+    }
+}
+
+/// config の閾値とスレッドプールで動く sort_by_with_control
+pub fn sort_by_with_control_config<T, F>(
+    x: &mut [T],
+    comparator: &F,
+    config: &SortConfig,
+    ctl: &SortControl,
+) -> Result<(), Cancelled>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    config.run(|| do_sort_with_control(x, true, comparator, config.threshold, ctl))
+}
+
+pub fn sort_with_control_config<T: Ord + Send>(
+    x: &mut [T],
+    order: &SortOrder,
+    config: &SortConfig,
+    ctl: &SortControl,
+) -> Result<(), Cancelled> {
+    match *order {
+        SortOrder::Ascending => sort_by_with_control_config(x, &|a, b| a.cmp(b), config, ctl),
+        SortOrder::Descending => sort_by_with_control_config(x, &|a, b| b.cmp(a), config, ctl),
+    }
+}
+
+pub fn sort_by_key<T, K, F>(x: &mut [T], key: &F)
+where
+    T: Send,
+    K: Ord,
+    F: Sync + Fn(&T) -> K,
+{
+    sort_by(x, &|a, b| key(a).cmp(&key(b)));
+}
+
+// キーは各要素につき1回だけ、並列に計算する
+pub fn sort_by_cached_key<T, K, F>(x: &mut [T], key: &F)
+where
+    T: Send + Sync,
+    K: Ord + Send,
+    F: Sync + Fn(&T) -> K,
+{
+    let mut keyed: Vec<(K, usize)> = x
+        .par_iter()
+        .map(key)
+        .enumerate()
+        .map(|(i, k)| (k, i))
+        .collect();
+    sort_by(&mut keyed, &|a, b| a.0.cmp(&b.0));
+    let mut indices: Vec<_> = keyed.into_iter().map(|(_, i)| i).collect();
+    apply_sorted_indices(x, &mut indices);
+}
+
+// 要素に元の位置を添えて比較するので、等しい要素の順序が保たれる
+pub fn stable_sort_by<T, F>(x: &mut [T], comparator: &F)
+where
+    T: Send + Sync,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    let mut indices = argsort_by(x, comparator);
+    apply_sorted_indices(x, &mut indices);
+}
+
+pub fn stable_sort<T: Ord + Send + Sync>(x: &mut [T], order: &SortOrder) {
+    match *order {
+        SortOrder::Ascending => stable_sort_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => stable_sort_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+/// f32/f64 を IEEE 754 の全順序で整列する。-0.0 は 0.0 より前に来る
+///
+/// NaN は nan に従って扱う。エラーのときは x を変更しない
+pub fn sort_floats<T: Float>(
+    x: &mut [T],
+    order: &SortOrder,
+    nan: NanPolicy,
+) -> Result<(), NanError> {
+    float::sort_keys_with(x, nan, |keys| sort(keys, order))
+}
+
+/// x を整列したときに i 番目に来る要素の元の位置を返す。等しい要素は元の順に並ぶ
+pub fn argsort_by<T, F>(x: &[T], comparator: &F) -> Vec<usize>
+where
+    T: Sync,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    let mut indices: Vec<usize> = (0..x.len()).collect();
+    sort_by(&mut indices, &|&a, &b| {
+        comparator(&x[a], &x[b]).then(a.cmp(&b))
+    });
+    indices
+}
+
+pub fn argsort<T: Ord + Sync>(x: &[T], order: &SortOrder) -> Vec<usize> {
+    match *order {
+        SortOrder::Ascending => argsort_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => argsort_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+/// keys を整列し、values の要素も keys の要素と同じ位置へ動かす
+pub fn sort_pairs_by<K, V, F>(keys: &mut [K], values: &mut [V], comparator: &F)
+where
+    K: Send,
+    V: Send,
+    F: Sync + Fn(&K, &K) -> Ordering,
+{
+    assert_eq!(
+        keys.len(),
+        values.len(),
+        "keys and values must have the same length"
+    );
+    do_sort_pairs(keys, values, true, comparator, PARALLEL_THRESHOLD);
+}
+
+pub fn sort_pairs<K: Ord + Send, V: Send>(keys: &mut [K], values: &mut [V], order: &SortOrder) {
+    match *order {
+        SortOrder::Ascending => sort_pairs_by(keys, values, &|a, b| a.cmp(b)),
+        SortOrder::Descending => sort_pairs_by(keys, values, &|a, b| b.cmp(a)),
+    }
+}
+
+pub(crate) const PARALLEL_THRESHOLD: usize = 4096;
+
+// 閾値の計測に使う定数
+const JOIN_COST_FACTOR: f64 = 32.0;
+const MIN_CALIBRATED_THRESHOLD: usize = 256;
+const MAX_CALIBRATED_THRESHOLD: usize = 1 << 20;
+const CALIBRATION_ROUNDS: u32 = 256;
+const CALIBRATION_LEN: usize = 4096;
+
+// rayon::join 1回にかかる時間 (ナノ秒)
+fn measure_join() -> f64 {
+    let start = Instant::now();
+    for _ in 0..CALIBRATION_ROUNDS {
+        rayon::join(|| (), || ());
+    }
+    start.elapsed().as_nanos() as f64 / CALIBRATION_ROUNDS as f64
+}
+
+// 1要素あたりの比較交換にかかる時間 (ナノ秒)
+fn measure_compare_and_swap() -> f64 {
+    let mut x = super::utils::new_u32_vec(CALIBRATION_LEN);
+    let start = Instant::now();
+    for round in 0..CALIBRATION_ROUNDS {
+        compare_and_swap(&mut x, CALIBRATION_LEN / 2, round % 2 == 0, &|a, b| {
+            a.cmp(b)
+        });
+    }
+    let nanos = start.elapsed().as_nanos() as f64;
+    (nanos / (CALIBRATION_ROUNDS as usize * CALIBRATION_LEN / 2) as f64).max(0.1)
+}
+
+fn do_sort<T, F>(x: &mut [T], forward: bool, comparetor: &F, threshold: usize)
 where
     T: Send,
     F: Sync + Fn(&T, &T) -> Ordering,
 {
     if x.len() > 1 {
+        // 前半を逆向き、後半を指定の向きに並べると、長さによらずバイトニック列になる
         let mid_point = x.len() / 2;
         let (first, second) = x.split_at_mut(mid_point);
-        if mid_point >= PARALLEL_THRESHOLD {
+        if mid_point >= threshold {
             rayon::join(
-                || do_sort(first, true, comparetor),
-                || do_sort(second, false, comparetor),
+                || do_sort(first, !forward, comparetor, threshold),
+                || do_sort(second, forward, comparetor, threshold),
             );
         } else {
-            do_sort(first, true, comparetor);
-            do_sort(second, false, comparetor);
+            do_sort(first, !forward, comparetor, threshold);
+            do_sort(second, forward, comparetor, threshold);
         }
-        sub_sort(x, forward, comparetor);
+        sub_sort(x, forward, comparetor, threshold);
     }
 }
 
-fn sub_sort<T, F>(x: &mut [T], fowrard: bool, comparator: &F)
+pub(crate) fn sub_sort<T, F>(x: &mut [T], fowrard: bool, comparator: &F, threshold: usize)
 where
     T: Send,
     F: Sync + Fn(&T, &T) -> Ordering,
 {
     if x.len() > 1 {
-        compare_and_swap(x, fowrard, comparator);
-        let mid_point = x.len() / 2;
+        // x.len() 未満で最大の2のべき乗で区切る。長さが2のべき乗なら半分になる
+        let mid_point = x.len().next_power_of_two() / 2;
+        compare_and_swap(x, mid_point, fowrard, comparator);
         let (first, second) = x.split_at_mut(mid_point);
-        if mid_point >= PARALLEL_THRESHOLD {
+        if mid_point >= threshold {
             rayon::join(
-                || sub_sort(first, fowrard, comparator),
-                || sub_sort(second, fowrard, comparator),
+                || sub_sort(first, fowrard, comparator, threshold),
+                || sub_sort(second, fowrard, comparator, threshold),
             );
+        } else {
+            sub_sort(first, fowrard, comparator, threshold);
+            sub_sort(second, fowrard, comparator, threshold);
         }
-        sub_sort(first, fowrard, comparator);
-        sub_sort(second, fowrard, comparator);
     }
 }
 
-fn compare_and_swap<T, F>(x: &mut [T], forward: bool, comparator: &F)
+fn compare_and_swap<T, F>(x: &mut [T], mid_point: usize, forward: bool, comparator: &F)
 where
     F: Fn(&T, &T) -> Ordering,
 {
@@ -77,45 +404,187 @@ where
     } else {
         Ordering::Less
     };
-    let mid_point = x.len() / 2;
-    for i in 0..mid_point {
+    for i in 0..x.len() - mid_point {
         if (comparator(&x[i], &x[mid_point + i])) == swap_condition {
             x.swap(i, mid_point + i);
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::sort;
-    use super::sort_by;
-    use crate::utils::{is_sorted_ascending, is_sorted_descending, new_u32_vec};
-    use crate::SortOrder::*;
+// 中断を確かめ、進み具合を数える部分列の最小の長さ
+const CONTROL_GRANULARITY: usize = 1024;
 
-    #[derive(Debug, PartialEq)]
-    struct Student {
-        first_name: String,
-        last_name: String,
-        age: u8,
+// do_sort と同じ。分割のたびに中断されていないか確かめ、マージが終わるたびに数える。
+// CONTROL_GRANULARITY 未満の部分列は中断を確かめずに整列し、その段をまとめて数える。
+// threshold は並列に処理するかどうかだけを決めるので、並列化しない設定でも中断できる
+fn do_sort_with_control<T, F>(
+    x: &mut [T],
+    forward: bool,
+    comparator: &F,
+    threshold: usize,
+    ctl: &SortControl,
+) -> Result<(), Cancelled>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    ctl.check()?;
+    if x.len() < CONTROL_GRANULARITY {
+        do_sort(x, forward, comparator, threshold);
+        let stages = SortControl::total_stages(x.len());
+        if stages > 0 {
+            ctl.finished_stages
+                .fetch_add(stages, AtomicOrdering::Relaxed);
+        }
+        return Ok(());
     }
+    let mid_point = x.len() / 2;
+    let (first, second) = x.split_at_mut(mid_point);
+    if mid_point >= threshold {
+        let (r1, r2) = rayon::join(
+            || do_sort_with_control(first, !forward, comparator, threshold, ctl),
+            || do_sort_with_control(second, forward, comparator, threshold, ctl),
+        );
+        r1.and(r2)?;
+    } else {
+        do_sort_with_control(first, !forward, comparator, threshold, ctl)?;
+        do_sort_with_control(second, forward, comparator, threshold, ctl)?;
+    }
+    sub_sort_with_control(x, forward, comparator, threshold, ctl)?;
+    ctl.finished_stages.fetch_add(1, AtomicOrdering::Relaxed);
+    Ok(())
+}
 
-    impl Student {
-        fn new(first_name: &str, last_name: &str, age: u8) -> Student {
-            Student {
-                first_name: first_name.to_string(),
-                last_name: last_name.to_string(),
-                age,
-            }
+// 大きな列のマージには時間が掛かるので、比較交換の前にも中断を確かめる
+fn sub_sort_with_control<T, F>(
+    x: &mut [T],
+    forward: bool,
+    comparator: &F,
+    threshold: usize,
+    ctl: &SortControl,
+) -> Result<(), Cancelled>
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    if x.len() < CONTROL_GRANULARITY {
+        sub_sort(x, forward, comparator, threshold);
+        return Ok(());
+    }
+    ctl.check()?;
+    let mid_point = x.len().next_power_of_two() / 2;
+    compare_and_swap(x, mid_point, forward, comparator);
+    let (first, second) = x.split_at_mut(mid_point);
+    if mid_point >= threshold {
+        let (r1, r2) = rayon::join(
+            || sub_sort_with_control(first, forward, comparator, threshold, ctl),
+            || sub_sort_with_control(second, forward, comparator, threshold, ctl),
+        );
+        r1.and(r2)
+    } else {
+        sub_sort_with_control(first, forward, comparator, threshold, ctl)?;
+        sub_sort_with_control(second, forward, comparator, threshold, ctl)
+    }
+}
+
+fn do_sort_pairs<K, V, F>(
+    keys: &mut [K],
+    values: &mut [V],
+    forward: bool,
+    comparator: &F,
+    threshold: usize,
+) where
+    K: Send,
+    V: Send,
+    F: Sync + Fn(&K, &K) -> Ordering,
+{
+    if keys.len() > 1 {
+        let mid_point = keys.len() / 2;
+        let (k1, k2) = keys.split_at_mut(mid_point);
+        let (v1, v2) = values.split_at_mut(mid_point);
+        if mid_point >= threshold {
+            rayon::join(
+                || do_sort_pairs(k1, v1, !forward, comparator, threshold),
+                || do_sort_pairs(k2, v2, forward, comparator, threshold),
+            );
+        } else {
+            do_sort_pairs(k1, v1, !forward, comparator, threshold);
+            do_sort_pairs(k2, v2, forward, comparator, threshold);
+        }
+        sub_sort_pairs(keys, values, forward, comparator, threshold);
+    }
+}
+
+fn sub_sort_pairs<K, V, F>(
+    keys: &mut [K],
+    values: &mut [V],
+    forward: bool,
+    comparator: &F,
+    threshold: usize,
+) where
+    K: Send,
+    V: Send,
+    F: Sync + Fn(&K, &K) -> Ordering,
+{
+    if keys.len() > 1 {
+        let mid_point = keys.len().next_power_of_two() / 2;
+        compare_and_swap_pairs(keys, values, mid_point, forward, comparator);
+        let (k1, k2) = keys.split_at_mut(mid_point);
+        let (v1, v2) = values.split_at_mut(mid_point);
+        if mid_point >= threshold {
+            rayon::join(
+                || sub_sort_pairs(k1, v1, forward, comparator, threshold),
+                || sub_sort_pairs(k2, v2, forward, comparator, threshold),
+            );
+        } else {
+            sub_sort_pairs(k1, v1, forward, comparator, threshold);
+            sub_sort_pairs(k2, v2, forward, comparator, threshold);
+        }
+    }
+}
+
+// keys の要素を交換するときに、values の同じ位置の要素も交換する
+fn compare_and_swap_pairs<K, V, F>(
+    keys: &mut [K],
+    values: &mut [V],
+    mid_point: usize,
+    forward: bool,
+    comparator: &F,
+) where
+    F: Fn(&K, &K) -> Ordering,
+{
+    let swap_condition = if forward {
+        Ordering::Greater
+    } else {
+        Ordering::Less
+    };
+    for i in 0..keys.len() - mid_point {
+        if (comparator(&keys[i], &keys[mid_point + i])) == swap_condition {
+            keys.swap(i, mid_point + i);
+            values.swap(i, mid_point + i);
         }
     }
+}
 
-    // impl PartialEq for Student {
-    //     fn eq(&self, other: &Self) -> bool {
-    //         self.first_name == other.first_name
-    //             && self.last_name == other.last_name
-    //             && self.age == other.age
-    //     }
-    // }
+#[cfg(test)]
+mod tests {
+    use super::sort;
+    use super::sort_by;
+    use super::PARALLEL_THRESHOLD;
+    use super::{apply_permutation, argsort, argsort_by, invert_permutation};
+    use super::{sort_by_cached_key, sort_by_key};
+    use super::{sort_by_with_config, sort_with_config, SortConfig};
+    use super::{sort_by_with_control, sort_with_control, Cancelled, SortControl};
+    use super::{sort_by_with_control_config, sort_with_control_config};
+    use super::{sort_floats, sort_pairs, sort_pairs_by};
+    use super::{stable_sort, stable_sort_by};
+    use crate::float::{NanError, NanPolicy};
+    use crate::utils::fixtures::{students, Student};
+    use crate::utils::{is_sorted_ascending, is_sorted_descending, new_u32_vec};
+    use crate::SortOrder::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering::Relaxed;
+    use std::sync::Arc;
 
     #[test]
     fn sort_students_by_age_ascending() {
@@ -125,7 +594,7 @@ mod tests {
         let ryosuke = Student::new("Ryosuke", "Hayashi", 17);
         let mut x = vec![&taro, &hanako, &kyoko, &ryosuke];
         let expected = vec![&hanako, &kyoko, &taro, &ryosuke];
-        assert_eq!(sort_by(&mut x, &|a, b| a.age.cmp(&b.age)), Ok(()));
+        sort_by(&mut x, &|a, b| a.age.cmp(&b.age));
         assert_eq!(x, expected);
     }
 
@@ -137,27 +606,132 @@ mod tests {
         let ryosuke = Student::new("Ryosuke", "Hayashi", 17);
         let mut x = vec![&taro, &hanako, &kyoko, &ryosuke];
         let expected = vec![&ryosuke, &kyoko, &hanako, &taro];
-        assert_eq!(
-            sort_by(&mut x, &|a, b| a
-                .last_name
+        sort_by(&mut x, &|a, b| {
+            a.last_name
                 .cmp(&b.last_name)
-                .then_with(|| a.first_name.cmp(&b.first_name))),
-            Ok(())
-        );
+                .then_with(|| a.first_name.cmp(&b.first_name))
+        });
         assert_eq!(x, expected);
     }
 
+    #[test]
+    fn sort_students_by_key() {
+        let [taro, hanako, kyoko, ryosuke] = students();
+        let mut x = vec![&taro, &hanako, &kyoko, &ryosuke];
+        sort_by_key(&mut x, &|s| s.age);
+        assert_eq!(x, vec![&hanako, &kyoko, &taro, &ryosuke]);
+        sort_by_key(&mut x, &|s| (s.last_name.clone(), s.first_name.clone()));
+        assert_eq!(x, vec![&ryosuke, &kyoko, &hanako, &taro]);
+    }
+
+    #[test]
+    fn sort_by_cached_key_computes_each_key_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let calls = AtomicUsize::new(0);
+        let mut x = new_u32_vec(1000);
+        let mut expected = x.clone();
+        expected.sort_by_key(|n| n.to_string());
+        sort_by_cached_key(&mut x, &|n| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            n.to_string()
+        });
+        assert_eq!(x, expected);
+        assert_eq!(calls.load(Ordering::Relaxed), 1000);
+    }
+
+    #[test]
+    fn stable_sort_students_keeps_previous_order() {
+        let taro = Student::new("Taro", "Yamada", 16);
+        let hanako = Student::new("Hanako", "Yamada", 14);
+        let kyoko = Student::new("Kyoko", "Ito", 15);
+        let ryosuke = Student::new("Ryosuke", "Hayashi", 17);
+        let jiro = Student::new("Jiro", "Ito", 16);
+        let yuko = Student::new("Yuko", "Hayashi", 15);
+        let mut x = vec![&taro, &hanako, &kyoko, &ryosuke, &jiro, &yuko];
+
+        // 名字で並べてから年齢で並べると、同じ年齢の中では名字の順が保たれる
+        stable_sort_by(&mut x, &|a, b| a.last_name.cmp(&b.last_name));
+        assert_eq!(x, vec![&ryosuke, &yuko, &kyoko, &jiro, &taro, &hanako]);
+        stable_sort_by(&mut x, &|a, b| a.age.cmp(&b.age));
+        assert_eq!(x, vec![&hanako, &yuko, &kyoko, &jiro, &taro, &ryosuke]);
+    }
+
+    #[test]
+    fn stable_sort_u32_by_equal_keys() {
+        // 上位ビットだけで比較し、下位ビットに元の位置を入れておく
+        let mut x: Vec<u32> = new_u32_vec(1000)
+            .into_iter()
+            .enumerate()
+            .map(|(i, n)| (n & 0xf000_0000) | i as u32)
+            .collect();
+        stable_sort_by(&mut x, &|a, b| (a >> 28).cmp(&(b >> 28)));
+        assert!(is_sorted_ascending(&x));
+
+        let mut y = vec![3, 1, 2, 1];
+        stable_sort(&mut y, &Descending);
+        assert_eq!(y, vec![3, 2, 1, 1]);
+    }
+
+    #[test]
+    fn sort_with_custom_threshold_and_pool() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .build()
+            .unwrap();
+        let pool = Arc::new(pool);
+        for threshold in [1, 7, 64, usize::MAX] {
+            let config = SortConfig::new()
+                .with_threshold(threshold)
+                .with_pool(pool.clone());
+            let mut x = new_u32_vec(3000);
+            let mut expected = x.clone();
+            expected.sort();
+            sort_with_config(&mut x, &Ascending, &config);
+            assert_eq!(x, expected);
+        }
+
+        let config = SortConfig::new().with_threshold(2);
+        let kyoko = Student::new("Kyoko", "Ito", 15);
+        let ryosuke = Student::new("Ryosuke", "Hayashi", 17);
+        let hanako = Student::new("Hanako", "Yamada", 14);
+        let mut x = vec![&kyoko, &ryosuke, &hanako];
+        sort_by_with_config(&mut x, &|a, b| b.age.cmp(&a.age), &config);
+        assert_eq!(x, vec![&ryosuke, &kyoko, &hanako]);
+    }
+
+    #[test]
+    fn calibrated_threshold() {
+        let single = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let config = SortConfig::new().with_pool(Arc::new(single)).calibrated();
+        assert_eq!(config.threshold(), usize::MAX);
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let config = SortConfig::new().with_pool(Arc::new(pool)).calibrated();
+        assert!(config.threshold().is_power_of_two());
+        assert!((256..=1 << 20).contains(&config.threshold()));
+
+        let mut x = new_u32_vec(65537);
+        sort_with_config(&mut x, &Descending, &config);
+        assert!(is_sorted_descending(&x));
+    }
+
     #[test]
     fn sort_u32_ascending() {
         let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
-        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+        sort(&mut x, &Ascending);
         assert_eq!(x, vec![4, 10, 11, 20, 21, 30, 110, 330]);
     }
 
     #[test]
     fn sort_u32_descending() {
         let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
-        assert_eq!(sort(&mut x, &Descending), Ok(()));
+        sort(&mut x, &Descending);
         assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
     }
 
@@ -173,7 +747,7 @@ mod tests {
             "no",
             "GC",
         ];
-        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+        sort(&mut x, &Ascending);
         assert_eq!(
             x,
             vec![
@@ -201,7 +775,7 @@ mod tests {
             "no",
             "GC",
         ];
-        assert_eq!(sort(&mut x, &Descending), Ok(()));
+        sort(&mut x, &Descending);
         assert_eq!(
             x,
             vec![
@@ -218,22 +792,243 @@ mod tests {
     }
 
     #[test]
-    fn sort_to_fail() {
+    fn sort_odd_length() {
         let mut x = vec![10, 30, 11];
-        assert!(sort(&mut x, &Ascending).is_err());
+        sort(&mut x, &Ascending);
+        assert_eq!(x, vec![10, 11, 30]);
+    }
+
+    #[test]
+    fn sort_u32_arbitrary_length() {
+        for len in 0..=130 {
+            let mut x = new_u32_vec(len);
+            let mut expected = x.clone();
+            expected.sort();
+            sort(&mut x, &Ascending);
+            assert_eq!(x, expected);
+            sort(&mut x, &Descending);
+            expected.reverse();
+            assert_eq!(x, expected);
+        }
+    }
+
+    #[test]
+    fn sort_u32_large_odd_length() {
+        for &len in &[3 * PARALLEL_THRESHOLD + 1, 65537, 100_000] {
+            let mut x = new_u32_vec(len);
+            let mut expected = x.clone();
+            expected.sort();
+            sort(&mut x, &Ascending);
+            assert_eq!(x, expected);
+            sort(&mut x, &Descending);
+            assert!(is_sorted_descending(&x));
+        }
     }
 
     #[test]
     fn sort_u32_large() {
         {
             let mut x = new_u32_vec(65536);
-            assert_eq!(sort(&mut x, &Ascending), Ok(()));
+            sort(&mut x, &Ascending);
             assert!(is_sorted_ascending(&x));
         }
         {
             let mut x = new_u32_vec(65536);
-            assert_eq!(sort(&mut x, &Descending), Ok(()));
+            sort(&mut x, &Descending);
             assert!(is_sorted_descending(&x));
         }
     }
+
+    #[test]
+    fn argsort_and_apply_permutation_to_columns() {
+        let keys: Vec<u32> = new_u32_vec(PARALLEL_THRESHOLD * 3 + 7)
+            .into_iter()
+            .map(|v| v % 100)
+            .collect();
+        let names: Vec<String> = (0..keys.len()).map(|i| format!("row{}", i)).collect();
+
+        let perm = argsort(&keys, &Descending);
+        let mut expected: Vec<usize> = (0..keys.len()).collect();
+        expected.sort_by(|&a, &b| keys[b].cmp(&keys[a]));
+        assert_eq!(perm, expected);
+
+        // 同じ並べ替えを複数の列に適用する
+        let (mut k, mut n) = (keys.clone(), names.clone());
+        apply_permutation(&mut k, &perm);
+        apply_permutation(&mut n, &perm);
+        assert!(is_sorted_descending(&k));
+        for (key, name) in k.iter().zip(&n) {
+            let row: usize = name[3..].parse().unwrap();
+            assert_eq!(keys[row], *key);
+        }
+
+        // 逆の並べ替えで元に戻る
+        apply_permutation(&mut n, &invert_permutation(&perm));
+        assert_eq!(n, names);
+        assert_eq!(
+            argsort_by(&keys, &|a, b| a.cmp(b)),
+            argsort(&keys, &Ascending)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn apply_invalid_permutation() {
+        apply_permutation(&mut [1, 2, 3], &[0, 0, 1]);
+    }
+
+    #[test]
+    fn sort_pairs_moves_values_with_keys() {
+        let keys: Vec<u32> = new_u32_vec(PARALLEL_THRESHOLD * 3 + 7)
+            .into_iter()
+            .map(|v| v % 50)
+            .collect();
+        let mut k = keys.clone();
+        let mut v: Vec<usize> = (0..keys.len()).collect();
+        sort_pairs(&mut k, &mut v, &Ascending);
+        assert!(is_sorted_ascending(&k));
+        assert!(v.iter().zip(&k).all(|(&i, key)| keys[i] == *key));
+
+        // 同じネットワークを通るので、組にして sort_by したときと同じ結果になる
+        let mut pairs: Vec<(u32, usize)> = keys.iter().copied().zip(0..).collect();
+        sort_by(&mut pairs, &|a, b| b.0.cmp(&a.0));
+        let (mut k, mut v) = (keys.clone(), (0..keys.len()).collect::<Vec<_>>());
+        sort_pairs_by(&mut k, &mut v, &|a, b| b.cmp(a));
+        assert_eq!(pairs, k.into_iter().zip(v).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sort_floats_with_nan_policy() {
+        let mut x: Vec<f64> = new_u32_vec(PARALLEL_THRESHOLD * 3 + 7)
+            .into_iter()
+            .map(|v| (v as i32) as f64 / 7.0)
+            .collect();
+        x.extend([
+            f64::NAN,
+            -0.0,
+            0.0,
+            f64::INFINITY,
+            -f64::NAN,
+            f64::NEG_INFINITY,
+        ]);
+        let bits = |v: &[f64]| v.iter().map(|f| f.to_bits()).collect::<Vec<_>>();
+        let mut numbers: Vec<f64> = x.iter().copied().filter(|v| !v.is_nan()).collect();
+        numbers.sort_by(|a, b| a.total_cmp(b));
+
+        let mut y = x.clone();
+        sort_floats(&mut y, &Ascending, NanPolicy::Last).unwrap();
+        assert_eq!(bits(&y[..numbers.len()]), bits(&numbers));
+        assert!(y[numbers.len()..].iter().all(|v| v.is_nan()));
+
+        let mut y = x.clone();
+        sort_floats(&mut y, &Descending, NanPolicy::First).unwrap();
+        assert!(y[..2].iter().all(|v| v.is_nan()));
+        numbers.reverse();
+        assert_eq!(bits(&y[2..]), bits(&numbers));
+
+        let mut y = x.clone();
+        let index = x.len() - 6;
+        assert_eq!(
+            sort_floats(&mut y, &Ascending, NanPolicy::Error),
+            Err(NanError { index })
+        );
+        assert_eq!(bits(&y), bits(&x));
+
+        let mut y = vec![2.5f32, -1.0, -0.0, 0.0];
+        sort_floats(&mut y, &Ascending, NanPolicy::Error).unwrap();
+        assert_eq!(y, [-1.0, -0.0, 0.0, 2.5]);
+        assert!(y[1].is_sign_negative());
+    }
+
+    #[test]
+    fn sort_with_control_reports_progress() {
+        let ctl = SortControl::new();
+        let mut x = new_u32_vec(PARALLEL_THRESHOLD * 3 + 7);
+        let mut expected = x.clone();
+        expected.sort_by(|a, b| b.cmp(a));
+        assert_eq!(sort_with_control(&mut x, &Descending, &ctl), Ok(()));
+        assert_eq!(x, expected);
+        assert_eq!(ctl.finished_stages(), SortControl::total_stages(x.len()));
+    }
+
+    #[test]
+    fn sort_with_control_stops_when_cancelled() {
+        let x = new_u32_vec(PARALLEL_THRESHOLD * 8);
+        let mut expected = x.clone();
+        expected.sort();
+
+        // 始める前に中断されていれば、何もしない
+        let ctl = SortControl::new();
+        ctl.cancel();
+        let mut y = x.clone();
+        assert_eq!(sort_with_control(&mut y, &Ascending, &ctl), Err(Cancelled));
+        assert_eq!(y, x);
+        assert_eq!(ctl.finished_stages(), 0);
+
+        // 途中で中断する。中断した後の比較は、最後のマージ1回分より少ない
+        let ctl = SortControl::new();
+        let compared = AtomicUsize::new(0);
+        let mut y = x.clone();
+        let result = sort_by_with_control(
+            &mut y,
+            &|a: &u32, b: &u32| {
+                if compared.fetch_add(1, Relaxed) == 100_000 {
+                    ctl.cancel();
+                }
+                a.cmp(b)
+            },
+            &ctl,
+        );
+        assert_eq!(result, Err(Cancelled));
+        assert_eq!(Cancelled.to_string(), "sort cancelled");
+        assert!(compared.load(Relaxed) < 100_000 + x.len());
+        assert!(ctl.finished_stages() < SortControl::total_stages(x.len()));
+        y.sort();
+        assert_eq!(y, expected);
+    }
+
+    #[test]
+    fn sort_with_control_uses_config() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let x = new_u32_vec(PARALLEL_THRESHOLD * 5 + 3);
+        let mut expected = x.clone();
+        expected.sort();
+        for config in [
+            SortConfig::new().with_threshold(64),
+            SortConfig::new().with_pool(Arc::new(pool)),
+            SortConfig::new().with_threshold(usize::MAX),
+        ] {
+            let ctl = SortControl::new();
+            let mut y = x.clone();
+            assert_eq!(
+                sort_with_control_config(&mut y, &Ascending, &config, &ctl),
+                Ok(())
+            );
+            assert_eq!(y, expected);
+            assert_eq!(ctl.finished_stages(), SortControl::total_stages(x.len()));
+
+            // プールの中で動き、並列化しない設定でも中断できる
+            let ctl = SortControl::new();
+            let threads = AtomicUsize::new(0);
+            let mut y = x.clone();
+            let result = sort_by_with_control_config(
+                &mut y,
+                &|a: &u32, b: &u32| {
+                    threads.store(rayon::current_num_threads(), Relaxed);
+                    ctl.cancel();
+                    a.cmp(b)
+                },
+                &config,
+                &ctl,
+            );
+            assert_eq!(result, Err(Cancelled));
+            assert!(ctl.finished_stages() < SortControl::total_stages(x.len()));
+            if config.pool.is_some() {
+                assert_eq!(threads.load(Relaxed), 2);
+            }
+        }
+    }
 }
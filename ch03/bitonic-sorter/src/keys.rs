@@ -0,0 +1,256 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+// 複数のキーと、キーごとの向きを組み合わせた比較関数を作る
+//
+//     let spec = by(|s: &Student| &s.last_name).asc().then_by(|s| &s.age).desc();
+//     third::sort_by(&mut x, &spec.comparator());
+//
+// 前のキーで等しかった要素だけを次のキーで比べる。asc/desc と nulls_first/nulls_last は
+// 直前に追加したキーに掛かる
+
+type CompareFn<'a, T> = Box<dyn Fn(&T, &T) -> Ordering + Send + Sync + 'a>;
+type IsNullFn<'a, T> = Box<dyn Fn(&T) -> bool + Send + Sync + 'a>;
+
+struct Key<'a, T> {
+    // どちらも None でないときの比較
+    compare: CompareFn<'a, T>,
+    // Option のキーなら、値が None かどうか
+    is_null: Option<IsNullFn<'a, T>>,
+    descending: bool,
+    nulls_first: bool,
+}
+
+impl<T> Key<'_, T> {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        if let Some(ref is_null) = self.is_null {
+            // None の位置は向きによらず nulls_first/nulls_last で決まる
+            match (is_null(a), is_null(b)) {
+                (true, true) => return Ordering::Equal,
+                (true, false) if self.nulls_first => return Ordering::Less,
+                (true, false) => return Ordering::Greater,
+                (false, true) if self.nulls_first => return Ordering::Greater,
+                (false, true) => return Ordering::Less,
+                (false, false) => {}
+            }
+        }
+        let ord = (self.compare)(a, b);
+        if self.descending {
+            ord.reverse()
+        } else {
+            ord
+        }
+    }
+}
+
+/// キーを並べた整列の指定
+///
+/// 各モジュールの sort_by には comparator() の結果を渡す。by と then_by は要素から
+/// 借用したキーを、by_key と then_by_key は要素から計算したキーを取る
+///
+/// ```
+/// use bitonic_sorter::keys::by;
+/// use bitonic_sorter::third;
+///
+/// struct Student {
+///     last_name: String,
+///     age: u8,
+/// }
+///
+/// let student = |last_name: &str, age| Student {
+///     last_name: last_name.to_string(),
+///     age,
+/// };
+/// let mut x = vec![student("Yamada", 14), student("Ito", 15), student("Yamada", 16)];
+///
+/// let spec = by(|s: &Student| &s.last_name).asc().then_by(|s| &s.age).desc();
+/// third::sort_by(&mut x, &spec.comparator());
+/// assert_eq!(x.iter().map(|s| s.age).collect::<Vec<_>>(), [15, 16, 14]);
+///
+/// // 計算したキーは then_by_key で渡す
+/// let spec = by(|s: &Student| &s.last_name).then_by_key(|s| s.age);
+/// third::sort_by(&mut x, &spec.comparator());
+/// assert_eq!(x.iter().map(|s| s.age).collect::<Vec<_>>(), [15, 14, 16]);
+/// ```
+pub struct KeySpec<'a, T> {
+    keys: Vec<Key<'a, T>>,
+}
+
+/// 要素から借用したキーで比べる
+pub fn by<'a, T, K, F>(key: F) -> KeySpec<'a, T>
+where
+    K: Ord + ?Sized,
+    F: Fn(&T) -> &K + Send + Sync + 'a,
+{
+    KeySpec { keys: Vec::new() }.then_by(key)
+}
+
+/// 要素から計算したキーで比べる
+pub fn by_key<'a, T, K, F>(key: F) -> KeySpec<'a, T>
+where
+    K: Ord,
+    F: Fn(&T) -> K + Send + Sync + 'a,
+{
+    KeySpec { keys: Vec::new() }.then_by_key(key)
+}
+
+/// Option のキーで比べる。None の位置は既定では最後 (nulls_last)
+pub fn by_opt<'a, T, K, F>(key: F) -> KeySpec<'a, T>
+where
+    K: Ord + ?Sized,
+    F: Fn(&T) -> Option<&K> + Send + Sync + 'a,
+{
+    KeySpec { keys: Vec::new() }.then_by_opt(key)
+}
+
+impl<'a, T> KeySpec<'a, T> {
+    pub fn then_by<K, F>(self, key: F) -> Self
+    where
+        K: Ord + ?Sized,
+        F: Fn(&T) -> &K + Send + Sync + 'a,
+    {
+        self.push(Box::new(move |a, b| key(a).cmp(key(b))), None)
+    }
+
+    pub fn then_by_key<K, F>(self, key: F) -> Self
+    where
+        K: Ord,
+        F: Fn(&T) -> K + Send + Sync + 'a,
+    {
+        self.push(Box::new(move |a, b| key(a).cmp(&key(b))), None)
+    }
+
+    pub fn then_by_opt<K, F>(self, key: F) -> Self
+    where
+        K: Ord + ?Sized,
+        F: Fn(&T) -> Option<&K> + Send + Sync + 'a,
+    {
+        let key = Arc::new(key);
+        let is_null = key.clone();
+        self.push(
+            Box::new(move |a, b| match (key(a), key(b)) {
+                (Some(a), Some(b)) => a.cmp(b),
+                _ => Ordering::Equal,
+            }),
+            Some(Box::new(move |x| is_null(x).is_none())),
+        )
+    }
+
+    /// 直前のキーを昇順にする (既定)
+    pub fn asc(mut self) -> Self {
+        self.last().descending = false;
+        self
+    }
+
+    /// 直前のキーを降順にする
+    pub fn desc(mut self) -> Self {
+        self.last().descending = true;
+        self
+    }
+
+    /// 直前のキーが None の要素を先頭に置く。Option でないキーには影響しない
+    pub fn nulls_first(mut self) -> Self {
+        self.last().nulls_first = true;
+        self
+    }
+
+    /// 直前のキーが None の要素を末尾に置く (既定)
+    pub fn nulls_last(mut self) -> Self {
+        self.last().nulls_first = false;
+        self
+    }
+
+    pub fn compare(&self, a: &T, b: &T) -> Ordering {
+        for key in &self.keys {
+            match key.compare(a, b) {
+                Ordering::Equal => {}
+                ord => return ord,
+            }
+        }
+        Ordering::Equal
+    }
+
+    /// sort_by に渡せる比較関数
+    pub fn comparator(&self) -> impl Fn(&T, &T) -> Ordering + Send + Sync + '_ {
+        move |a, b| self.compare(a, b)
+    }
+
+    fn push(mut self, compare: CompareFn<'a, T>, is_null: Option<IsNullFn<'a, T>>) -> Self {
+        self.keys.push(Key {
+            compare,
+            is_null,
+            descending: false,
+            nulls_first: false,
+        });
+        self
+    }
+
+    fn last(&mut self) -> &mut Key<'a, T> {
+        // by 系の関数で作るので、キーは少なくとも1つある
+        self.keys.last_mut().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{by, by_key, by_opt};
+    use crate::network::Network;
+    use crate::utils::fixtures::{students, Student};
+    use crate::utils::new_u32_vec;
+    use crate::{fourth, third};
+
+    #[test]
+    fn sort_students_by_last_name_then_age_desc() {
+        let [taro, hanako, kyoko, ryosuke] = students();
+        let x = vec![&taro, &hanako, &kyoko, &ryosuke];
+        let expected = vec![&ryosuke, &kyoko, &taro, &hanako];
+
+        let spec = by(|s: &&Student| &s.last_name)
+            .asc()
+            .then_by(|s| &s.age)
+            .desc();
+        let mut y = x.clone();
+        third::sort_by(&mut y, &spec.comparator());
+        assert_eq!(y, expected);
+        let mut y = x.clone();
+        fourth::sort_by(&mut y, &spec.comparator());
+        assert_eq!(y, expected);
+        let mut y = x.clone();
+        Network::bitonic(y.len()).sort_by(&mut y, &spec.comparator());
+        assert_eq!(y, expected);
+    }
+
+    #[test]
+    fn sort_option_keys_with_nulls_first_and_last() {
+        let [taro, hanako, kyoko, ryosuke] = students();
+        let x = vec![&taro, &hanako, &kyoko, &ryosuke];
+
+        let mut y = x.clone();
+        let spec = by_opt(|s: &&Student| s.club.as_deref()).then_by(|s| &s.first_name);
+        third::sort_by(&mut y, &spec.comparator());
+        assert_eq!(y, vec![&kyoko, &taro, &hanako, &ryosuke]);
+
+        // None の位置は desc の影響を受けない
+        let spec = by_opt(|s: &&Student| s.club.as_deref())
+            .desc()
+            .nulls_first()
+            .then_by(|s| &s.first_name)
+            .desc();
+        fourth::sort_by(&mut y, &spec.comparator());
+        assert_eq!(y, vec![&ryosuke, &hanako, &taro, &kyoko]);
+    }
+
+    #[test]
+    fn spec_matches_tuple_comparison() {
+        let x: Vec<(u32, u32)> = new_u32_vec(3000)
+            .into_iter()
+            .map(|v| (v % 7, v / 7 % 100))
+            .collect();
+        let spec = by_key(|p: &(u32, u32)| p.0).desc().then_by_key(|p| p.1);
+        let mut expected = x.clone();
+        expected.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        let mut y = x.clone();
+        fourth::sort_by(&mut y, &spec.comparator());
+        assert_eq!(y, expected);
+    }
+}
@@ -1,6 +1,14 @@
+pub mod external;
 pub mod first;
+pub mod float;
 pub mod fourth;
+pub mod keys;
+pub mod merge;
+pub mod network;
+pub mod radix;
 pub mod second;
+pub mod select;
+pub mod simd;
 pub mod third;
 pub mod utils;
 
@@ -8,3 +16,120 @@ pub enum SortOrder {
     Ascending,
     Descending,
 }
+
+// すべての実装に同じ性質を課す、生成した入力によるテスト
+#[cfg(test)]
+mod tests {
+    use super::network::Network;
+    use super::SortOrder::{self, *};
+    use super::{first, fourth, radix, second, simd, third};
+    use proptest::prelude::*;
+
+    type Sorter = fn(&mut [u32], &SortOrder);
+
+    fn sorters() -> Vec<(&'static str, Sorter)> {
+        vec![
+            ("first", |x, order| {
+                first::sort(x, matches!(*order, Ascending))
+            }),
+            ("second", second::sort),
+            ("third", third::sort),
+            ("fourth", fourth::sort),
+            ("third::stable", third::stable_sort),
+            ("fourth::stable", fourth::stable_sort),
+            ("simd", simd::sort),
+            ("simd::par", simd::par_sort),
+            ("network", |x, order| {
+                Network::bitonic(x.len()).sort(x, order)
+            }),
+            ("radix", radix::sort),
+        ]
+    }
+
+    // 重複の多い列と、値の範囲が広い列の両方を作る
+    fn inputs(max_len: usize) -> impl Strategy<Value = Vec<u32>> {
+        prop_oneof![
+            prop::collection::vec(any::<u32>(), 0..max_len),
+            prop::collection::vec(0u32..8, 0..max_len),
+        ]
+    }
+
+    fn check_properties(x: &[u32]) -> Result<(), TestCaseError> {
+        let mut expected = x.to_vec();
+        expected.sort();
+        for (name, sort) in sorters() {
+            let mut asc = x.to_vec();
+            sort(&mut asc, &Ascending);
+            // std の結果と一致するので、入力の並べ替えにもなっている
+            prop_assert_eq!(&asc, &expected, "{} ascending", name);
+
+            let mut desc = x.to_vec();
+            sort(&mut desc, &Descending);
+            desc.reverse();
+            prop_assert_eq!(&desc, &asc, "{}: descending is not the reverse", name);
+
+            let mut again = asc.clone();
+            sort(&mut again, &Ascending);
+            prop_assert_eq!(&again, &asc, "{} is not idempotent", name);
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn all_sorters_agree_with_std(x in inputs(300)) {
+            check_properties(&x)?;
+        }
+
+        #[test]
+        fn outputs_are_permutations(x in inputs(300)) {
+            for (name, sort) in sorters() {
+                let mut y = x.clone();
+                sort(&mut y, &Descending);
+                let (mut a, mut b) = (x.clone(), y);
+                a.sort_unstable();
+                b.sort_unstable();
+                prop_assert_eq!(a, b, "{} lost or duplicated elements", name);
+            }
+        }
+
+        #[test]
+        fn comparator_paths_agree_with_std(
+            x in prop::collection::vec((0u8..16, any::<u16>()), 0..300),
+        ) {
+            // キーだけで比べる。安定な版は std の sort_by と完全に一致する
+            let by_key = |a: &(u8, u16), b: &(u8, u16)| b.0.cmp(&a.0);
+            let mut expected = x.clone();
+            expected.sort_by(by_key);
+
+            let mut y = x.clone();
+            third::stable_sort_by(&mut y, &by_key);
+            prop_assert_eq!(&y, &expected);
+            let mut y = x.clone();
+            fourth::stable_sort_by(&mut y, &by_key);
+            prop_assert_eq!(&y, &expected);
+
+            // 安定でない版は、キーの順と要素の集合だけが一致する
+            let keys = |v: &[(u8, u16)]| v.iter().map(|p| p.0).collect::<Vec<_>>();
+            for sort in [third::sort_by, fourth::sort_by] {
+                let mut y = x.clone();
+                sort(&mut y, &by_key);
+                prop_assert_eq!(keys(&y), keys(&expected));
+                y.sort();
+                let mut sorted = x.clone();
+                sorted.sort();
+                prop_assert_eq!(y, sorted);
+            }
+        }
+    }
+
+    proptest! {
+        // fourth、simd と radix の並列の経路を通る長さ
+        #![proptest_config(ProptestConfig::with_cases(6))]
+
+        #[test]
+        fn parallel_paths_agree_with_std(x in inputs(12000)) {
+            check_properties(&x)?;
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use super::fourth::{self, PARALLEL_THRESHOLD};
+use rayon::prelude::*;
+use std::cmp::Ordering;
+
+// 整列済みの列を、整列し直さずにまとめる
+//
+// merge_sorted は列を半分ずつに分けて再帰的にマージする。2つの列のマージでは
+// 出力を同じ長さの区間に分け、区間の境目に来る a と b の位置を二分探索で求める
+// (merge path)。区間ごとのマージは互いに独立なので並列に実行できる
+
+/// 整列済みの runs をマージして out に書き出す
+///
+/// out の長さは runs の長さの合計と等しくなければならない。
+/// 等しい要素は runs の前の列にあるものが先に来る
+pub fn merge_sorted<T, F>(runs: &[&[T]], out: &mut [T], comparator: &F)
+where
+    T: Clone + Send + Sync,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    let total: usize = runs.iter().map(|r| r.len()).sum();
+    assert_eq!(out.len(), total, "output length must match the runs");
+    match runs.len() {
+        0 => {}
+        1 => out.clone_from_slice(runs[0]),
+        2 => merge_two(runs[0], runs[1], out, comparator),
+        _ => {
+            let (left, right) = runs.split_at(runs.len() / 2);
+            let (a, b) = rayon::join(
+                || merge_to_vec(left, comparator),
+                || merge_to_vec(right, comparator),
+            );
+            merge_two(&a, &b, out, comparator);
+        }
+    }
+}
+
+/// 昇順の x[..mid] と x[mid..] を、その場でマージする
+///
+/// 前半を反転するとバイトニック列になるので、fourth の sub_sort で整列できる
+pub fn bitonic_merge<T, F>(x: &mut [T], mid: usize, comparator: &F)
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    assert!(
+        mid <= x.len(),
+        "mid {} out of range for length {}",
+        mid,
+        x.len()
+    );
+    x[..mid].reverse();
+    fourth::sub_sort(x, true, comparator, PARALLEL_THRESHOLD);
+}
+
+fn merge_to_vec<T, F>(runs: &[&[T]], comparator: &F) -> Vec<T>
+where
+    T: Clone + Send + Sync,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    let first = match runs.iter().find(|r| !r.is_empty()) {
+        Some(run) => run[0].clone(),
+        None => return Vec::new(),
+    };
+    let total = runs.iter().map(|r| r.len()).sum();
+    // 中身は merge_sorted で上書きされる
+    let mut out = vec![first; total];
+    merge_sorted(runs, &mut out, comparator);
+    out
+}
+
+// a と b をマージする。出力を PARALLEL_THRESHOLD ずつの区間に分けて並列に処理する
+fn merge_two<T, F>(a: &[T], b: &[T], out: &mut [T], comparator: &F)
+where
+    T: Clone + Send + Sync,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    out.par_chunks_mut(PARALLEL_THRESHOLD)
+        .enumerate()
+        .for_each(|(k, chunk)| {
+            let start = k * PARALLEL_THRESHOLD;
+            let end = start + chunk.len();
+            let (i0, i1) = (
+                split_point(a, b, start, comparator),
+                split_point(a, b, end, comparator),
+            );
+            let (j0, j1) = (start - i0, end - i1);
+            merge_sequential(&a[i0..i1], &b[j0..j1], chunk, comparator);
+        });
+}
+
+// マージした結果の先頭 d 個に含まれる a の要素の数を求める
+fn split_point<T, F>(a: &[T], b: &[T], d: usize, comparator: &F) -> usize
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    let mut lo = d.saturating_sub(b.len());
+    let mut hi = d.min(a.len());
+    while lo < hi {
+        let i = (lo + hi) / 2;
+        // a[i] が b[d - i - 1] 以下なら、a[i] も先頭 d 個に含まれる
+        if comparator(&b[d - i - 1], &a[i]) != Ordering::Less {
+            lo = i + 1;
+        } else {
+            hi = i;
+        }
+    }
+    lo
+}
+
+fn merge_sequential<T, F>(a: &[T], b: &[T], out: &mut [T], comparator: &F)
+where
+    T: Clone,
+    F: Fn(&T, &T) -> Ordering,
+{
+    let (mut i, mut j) = (0, 0);
+    for slot in out.iter_mut() {
+        // 等しいときは a を先に出す
+        if j == b.len() || (i < a.len() && comparator(&b[j], &a[i]) != Ordering::Less) {
+            *slot = a[i].clone();
+            i += 1;
+        } else {
+            *slot = b[j].clone();
+            j += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bitonic_merge, merge_sorted};
+    use crate::utils::new_u32_vec;
+
+    fn sorted_runs(lens: &[usize]) -> Vec<Vec<u32>> {
+        let mut seed = 0;
+        lens.iter()
+            .map(|&len| {
+                let mut run: Vec<u32> = new_u32_vec(len + seed).split_off(seed);
+                seed += 1;
+                run.iter_mut().for_each(|v| *v %= 1000);
+                run.sort();
+                run
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merge_sorted_matches_sort() {
+        for lens in [
+            vec![],
+            vec![0],
+            vec![10],
+            vec![5, 0, 7],
+            vec![10000, 3],
+            vec![4096, 4096, 1, 9000, 0, 12345, 17],
+        ] {
+            let runs = sorted_runs(&lens);
+            let refs: Vec<&[u32]> = runs.iter().map(|r| r.as_slice()).collect();
+            let mut expected = runs.concat();
+            expected.sort();
+            let mut out = vec![0; expected.len()];
+            merge_sorted(&refs, &mut out, &|a, b| a.cmp(b));
+            assert_eq!(out, expected, "lens: {:?}", lens);
+        }
+    }
+
+    #[test]
+    fn merge_sorted_is_stable() {
+        // キーが等しければ、前の列の要素が先に来る
+        let runs: Vec<Vec<(u32, usize)>> = (0..5)
+            .map(|r| {
+                let mut run: Vec<_> = new_u32_vec(3000).into_iter().map(|v| (v % 10, r)).collect();
+                run.sort();
+                run
+            })
+            .collect();
+        let refs: Vec<&[(u32, usize)]> = runs.iter().map(|r| r.as_slice()).collect();
+        let mut expected = runs.concat();
+        expected.sort();
+        let mut out = vec![(0, 0); expected.len()];
+        merge_sorted(&refs, &mut out, &|a, b| a.0.cmp(&b.0));
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    #[should_panic]
+    fn merge_sorted_with_wrong_output_length() {
+        let mut out = [0; 3];
+        merge_sorted(&[&[1, 2], &[3, 4]], &mut out, &|a: &i32, b| a.cmp(b));
+    }
+
+    #[test]
+    fn bitonic_merge_halves() {
+        for len in [0, 1, 2, 3, 10, 100, 5000, 10007] {
+            for mid in [0, len / 3, len / 2, len] {
+                let mut x = new_u32_vec(len);
+                x[..mid].sort();
+                x[mid..].sort();
+                let mut expected = x.clone();
+                expected.sort();
+                bitonic_merge(&mut x, mid, &|a, b| a.cmp(b));
+                assert_eq!(x, expected, "len: {}, mid: {}", len, mid);
+            }
+        }
+        // 降順の比較関数でも同じように使える
+        let mut x = vec![9, 5, 1, 8, 6, 4, 2];
+        bitonic_merge(&mut x, 3, &|a, b| b.cmp(a));
+        assert_eq!(x, [9, 8, 6, 5, 4, 2, 1]);
+    }
+}
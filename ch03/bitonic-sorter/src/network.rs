@@ -0,0 +1,338 @@
+use super::SortOrder;
+use std::cmp::Ordering;
+use std::fmt::Write;
+
+// バイトニックソートの比較器ネットワークを明示的なデータとして扱う
+//
+// third の do_sort/sub_sort と同じ再帰で比較器を並べ、互いに独立な比較器を
+// 同じステージにまとめる。同じ線を使う比較器の順序は変えないので、
+// ネットワークを実行した結果は third::sort_by と一致する
+
+/// 比較器の向き
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// 小さい方を i に、大きい方を j に置く
+    Up,
+    /// 大きい方を i に、小さい方を j に置く
+    Down,
+}
+
+/// 線 i と線 j をつなぐ比較器。常に i < j
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Comparator {
+    pub i: usize,
+    pub j: usize,
+    pub direction: Direction,
+}
+
+/// n 本の線に対する比較器ネットワーク
+///
+/// 各ステージの比較器は互いに異なる線を使うので、同時に実行できる
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Network {
+    n: usize,
+    stages: Vec<Vec<Comparator>>,
+}
+
+impl Network {
+    /// 長さ n の列を整列するバイトニックネットワークを作る
+    pub fn bitonic(n: usize) -> Self {
+        let mut comparators = Vec::new();
+        do_sort(0, n, true, &mut comparators);
+        Self::from_comparators(n, comparators)
+    }
+
+    /// 比較器を実行する順に並べた列からネットワークを作る
+    ///
+    /// それぞれの比較器は、使う2本の線が最後に使われたステージの次のステージに置かれる
+    pub fn from_comparators(n: usize, comparators: Vec<Comparator>) -> Self {
+        let mut stages: Vec<Vec<Comparator>> = Vec::new();
+        // 線ごとに、次に置けるステージの番号
+        let mut next_stage = vec![0; n];
+        for c in comparators {
+            assert!(c.i < c.j && c.j < n, "invalid comparator: {:?}", c);
+            let stage = next_stage[c.i].max(next_stage[c.j]);
+            if stage == stages.len() {
+                stages.push(Vec::new());
+            }
+            stages[stage].push(c);
+            next_stage[c.i] = stage + 1;
+            next_stage[c.j] = stage + 1;
+        }
+        Network { n, stages }
+    }
+
+    /// 線の数
+    pub fn len(&self) -> usize {
+        self.n
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    /// ステージの数 (ネットワークの深さ)
+    pub fn depth(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn stages(&self) -> &[Vec<Comparator>] {
+        &self.stages
+    }
+
+    /// すべての比較器を実行する順に返す
+    pub fn comparators(&self) -> impl Iterator<Item = &Comparator> {
+        self.stages.iter().flatten()
+    }
+
+    /// ネットワークを x に適用する。x の長さは線の数と等しくなければならない
+    pub fn sort_by<T, F>(&self, x: &mut [T], comparator: &F)
+    where
+        F: Fn(&T, &T) -> Ordering,
+    {
+        assert_eq!(x.len(), self.n, "slice length must match the network");
+        for c in self.comparators() {
+            let swap_condition = match c.direction {
+                Direction::Up => Ordering::Greater,
+                Direction::Down => Ordering::Less,
+            };
+            if comparator(&x[c.i], &x[c.j]) == swap_condition {
+                x.swap(c.i, c.j);
+            }
+        }
+    }
+
+    pub fn sort<T: Ord>(&self, x: &mut [T], order: &SortOrder) {
+        match *order {
+            SortOrder::Ascending => self.sort_by(x, &|a, b| a.cmp(b)),
+            SortOrder::Descending => self.sort_by(x, &|a, b| b.cmp(a)),
+        }
+    }
+
+    /// Graphviz の DOT 形式で出力する
+    ///
+    /// 線 i のステージ s の位置を点 w{i}_{s} で表し、比較器は大きい値が移る線に向かう矢印になる
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph bitonic_{} {{", self.n).unwrap();
+        writeln!(dot, "  rankdir=LR;").unwrap();
+        writeln!(dot, "  node [shape=point];").unwrap();
+        for s in 0..=self.depth() {
+            let nodes: Vec<_> = (0..self.n).map(|i| format!("w{}_{}", i, s)).collect();
+            writeln!(dot, "  {{ rank=same; {}; }}", nodes.join("; ")).unwrap();
+        }
+        for i in 0..self.n {
+            let wire: Vec<_> = (0..=self.depth())
+                .map(|s| format!("w{}_{}", i, s))
+                .collect();
+            writeln!(dot, "  {} [arrowhead=none];", wire.join(" -> ")).unwrap();
+        }
+        for (s, stage) in self.stages.iter().enumerate() {
+            for c in stage {
+                let (lo, hi) = match c.direction {
+                    Direction::Up => (c.i, c.j),
+                    Direction::Down => (c.j, c.i),
+                };
+                writeln!(
+                    dot,
+                    "  w{}_{} -> w{}_{} [constraint=false, color=red];",
+                    lo,
+                    s + 1,
+                    hi,
+                    s + 1
+                )
+                .unwrap();
+            }
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+
+    /// SVG で出力する
+    ///
+    /// 線を横線、比較器を縦線で描き、大きい値が移る線の側に矢印を付ける。
+    /// 同じステージで縦に重なる比較器は列をずらして描く
+    pub fn to_svg(&self) -> String {
+        const MARGIN: usize = 20;
+        const WIRE_GAP: usize = 20;
+        const COLUMN_WIDTH: usize = 12;
+        const STAGE_GAP: usize = 12;
+
+        let layouts: Vec<_> = self.stages.iter().map(|s| layout_stage(s)).collect();
+        let columns: usize = layouts.iter().map(|(_, n)| n).sum();
+        let width = 2 * MARGIN + columns * COLUMN_WIDTH + self.depth() * STAGE_GAP;
+        let height = 2 * MARGIN + self.n.saturating_sub(1) * WIRE_GAP;
+        let y_of = |wire: usize| MARGIN + wire * WIRE_GAP;
+
+        let mut svg = String::new();
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+            width, height, width, height
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r#"  <defs><marker id="arrow" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="6" markerHeight="6" orient="auto"><path d="M0,0 L10,5 L0,10 z"/></marker></defs>"#
+        )
+        .unwrap();
+        for i in 0..self.n {
+            writeln!(
+                svg,
+                r#"  <line x1="{}" y1="{}" x2="{}" y2="{}" stroke="black"/>"#,
+                MARGIN / 2,
+                y_of(i),
+                width - MARGIN / 2,
+                y_of(i)
+            )
+            .unwrap();
+        }
+        let mut left = MARGIN + STAGE_GAP / 2;
+        for (stage, (offsets, count)) in self.stages.iter().zip(&layouts) {
+            for (c, offset) in stage.iter().zip(offsets) {
+                let x = left + offset * COLUMN_WIDTH + COLUMN_WIDTH / 2;
+                let (from, to) = match c.direction {
+                    Direction::Up => (c.i, c.j),
+                    Direction::Down => (c.j, c.i),
+                };
+                writeln!(svg, r#"  <circle cx="{}" cy="{}" r="3"/>"#, x, y_of(from)).unwrap();
+                writeln!(
+                    svg,
+                    r#"  <line x1="{}" y1="{}" x2="{}" y2="{}" stroke="black" marker-end="url(#arrow)"/>"#,
+                    x,
+                    y_of(from),
+                    x,
+                    y_of(to)
+                )
+                .unwrap();
+            }
+            left += count * COLUMN_WIDTH + STAGE_GAP;
+        }
+        writeln!(svg, "</svg>").unwrap();
+        svg
+    }
+}
+
+// ステージ内で縦に重なる比較器が同じ列に来ないように列を割り当てる。
+// 比較器ごとの列の番号と、列の数を返す
+fn layout_stage(stage: &[Comparator]) -> (Vec<usize>, usize) {
+    let mut columns: Vec<Vec<(usize, usize)>> = Vec::new();
+    let mut offsets = Vec::with_capacity(stage.len());
+    for c in stage {
+        let free = columns
+            .iter()
+            .position(|spans| spans.iter().all(|&(i, j)| c.j < i || j < c.i));
+        let k = match free {
+            Some(k) => k,
+            None => {
+                columns.push(Vec::new());
+                columns.len() - 1
+            }
+        };
+        columns[k].push((c.i, c.j));
+        offsets.push(k);
+    }
+    (offsets, columns.len())
+}
+
+// third::do_sort と同じ再帰で、x[start..start + len] を整列する比較器を並べる
+fn do_sort(start: usize, len: usize, up: bool, out: &mut Vec<Comparator>) {
+    if len > 1 {
+        let mid_point = len / 2;
+        do_sort(start, mid_point, !up, out);
+        do_sort(start + mid_point, len - mid_point, up, out);
+        sub_sort(start, len, up, out);
+    }
+}
+
+fn sub_sort(start: usize, len: usize, up: bool, out: &mut Vec<Comparator>) {
+    if len > 1 {
+        let mid_point = len.next_power_of_two() / 2;
+        let direction = if up { Direction::Up } else { Direction::Down };
+        for i in 0..len - mid_point {
+            out.push(Comparator {
+                i: start + i,
+                j: start + mid_point + i,
+                direction,
+            });
+        }
+        sub_sort(start, mid_point, up, out);
+        sub_sort(start + mid_point, len - mid_point, up, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Comparator, Direction, Network};
+    use crate::third;
+    use crate::utils::new_u32_vec;
+    use crate::SortOrder::*;
+
+    #[test]
+    fn network_of_four() {
+        let network = Network::bitonic(4);
+        let c = |i, j, direction| Comparator { i, j, direction };
+        assert_eq!(
+            network.stages(),
+            &[
+                vec![c(0, 1, Direction::Down), c(2, 3, Direction::Up)],
+                vec![c(0, 2, Direction::Up), c(1, 3, Direction::Up)],
+                vec![c(0, 1, Direction::Up), c(2, 3, Direction::Up)],
+            ]
+        );
+        assert_eq!(network.depth(), 3);
+    }
+
+    #[test]
+    fn network_matches_third_sort() {
+        for n in 0..=70 {
+            let network = Network::bitonic(n);
+            let mut x = new_u32_vec(n);
+            let mut expected = x.clone();
+            third::sort(&mut expected, &Ascending);
+            network.sort(&mut x, &Ascending);
+            assert_eq!(x, expected, "n: {}", n);
+
+            // 比較関数が同じなら、等しい要素の並びも third と一致する
+            let mut x: Vec<(u32, usize)> =
+                new_u32_vec(n).into_iter().map(|v| v % 4).zip(0..).collect();
+            let mut expected = x.clone();
+            third::sort_by(&mut expected, &|a, b| b.0.cmp(&a.0));
+            network.sort_by(&mut x, &|a, b| b.0.cmp(&a.0));
+            assert_eq!(x, expected, "n: {}", n);
+        }
+    }
+
+    #[test]
+    fn stages_use_distinct_wires() {
+        let network = Network::bitonic(37);
+        for stage in network.stages() {
+            let mut wires: Vec<_> = stage.iter().flat_map(|c| [c.i, c.j]).collect();
+            wires.sort();
+            wires.dedup();
+            assert_eq!(wires.len(), stage.len() * 2);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn sort_with_wrong_length_panics() {
+        Network::bitonic(8).sort(&mut [1, 2, 3], &Ascending);
+    }
+
+    #[test]
+    fn render_dot_and_svg() {
+        let network = Network::bitonic(4);
+        let dot = network.to_dot();
+        assert!(dot.starts_with("digraph bitonic_4 {"));
+        // Down の比較器は j から i に向かう
+        assert!(dot.contains("w1_1 -> w0_1 [constraint=false, color=red];"));
+        assert!(dot.contains("w0_3 -> w1_3 [constraint=false, color=red];"));
+
+        let svg = network.to_svg();
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<circle").count(), 6);
+        assert_eq!(svg.matches("marker-end").count(), 6);
+    }
+}
@@ -0,0 +1,228 @@
+use super::fourth::PARALLEL_THRESHOLD;
+use super::SortOrder;
+use rayon::prelude::*;
+
+// 整数とバイト列を LSD 基数ソートで整列する
+//
+// 鍵を上の桁から 0..RADIX の数字の列とみなし、下の桁から順に安定な数え上げソートを繰り返す。
+// 1回の数え上げは、列をスレッド数ほどのチャンクに分けて
+//   1. チャンクごとに数字の出現回数を数え (並列)
+//   2. 数字の順、同じ数字ならチャンクの順に書き込み先の位置を決め
+//   3. チャンクごとに要素を書き込み先に移す (並列)
+// の3段階で行う。どのチャンクも書き込む位置は重ならない
+
+// バイト列では、桁がない (短い) ことを表す 0 を加えて 257 通りになる
+const RADIX: usize = 257;
+
+/// 基数ソートで整列できる鍵の型
+///
+/// 符号なし整数、符号付き整数と、バイト列 (&[u8] と &str) に実装されている。
+/// バイト列は辞書順に並ぶ
+pub trait RadixKey: Copy + Ord + Send + Sync {
+    /// 桁の数
+    #[doc(hidden)]
+    fn digits(&self) -> usize;
+
+    /// 上から i 番目の桁の数字。0..257 の範囲で、大小が鍵の順序と一致する
+    #[doc(hidden)]
+    fn digit(&self, i: usize) -> usize;
+}
+
+macro_rules! impl_radix_key_unsigned {
+    ($($t:ty),*) => {$(
+        impl RadixKey for $t {
+            fn digits(&self) -> usize {
+                std::mem::size_of::<$t>()
+            }
+
+            fn digit(&self, i: usize) -> usize {
+                let shift = 8 * (std::mem::size_of::<$t>() - 1 - i);
+                ((*self >> shift) & 0xff) as usize
+            }
+        }
+    )*};
+}
+
+macro_rules! impl_radix_key_signed {
+    ($($t:ty => $u:ty),*) => {$(
+        impl RadixKey for $t {
+            fn digits(&self) -> usize {
+                std::mem::size_of::<$t>()
+            }
+
+            fn digit(&self, i: usize) -> usize {
+                // 符号ビットを反転すると、符号なしの大小と一致する
+                ((*self as $u) ^ (1 << (<$u>::BITS - 1))).digit(i)
+            }
+        }
+    )*};
+}
+
+impl_radix_key_unsigned!(u8, u16, u32, u64, u128, usize);
+impl_radix_key_signed!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128, isize => usize);
+
+impl RadixKey for &[u8] {
+    fn digits(&self) -> usize {
+        self.len()
+    }
+
+    fn digit(&self, i: usize) -> usize {
+        self.get(i).map_or(0, |&b| b as usize + 1)
+    }
+}
+
+impl RadixKey for &str {
+    fn digits(&self) -> usize {
+        self.len()
+    }
+
+    fn digit(&self, i: usize) -> usize {
+        self.as_bytes().digit(i)
+    }
+}
+
+pub fn sort<T: RadixKey>(x: &mut [T], order: &SortOrder) {
+    if x.len() <= 1 {
+        return;
+    }
+    let digits = x.par_iter().map(|v| v.digits()).max().unwrap();
+    let chunk_len = PARALLEL_THRESHOLD.max(x.len().div_ceil(rayon::current_num_threads()));
+    let mut buf = x.to_vec();
+    let mut in_buf = false;
+    for i in (0..digits).rev() {
+        let (src, dst) = if in_buf {
+            (&buf[..], &mut x[..])
+        } else {
+            (&x[..], &mut buf[..])
+        };
+        if counting_sort(src, dst, i, chunk_len) {
+            in_buf = !in_buf;
+        }
+    }
+    if in_buf {
+        x.copy_from_slice(&buf);
+    }
+    if let SortOrder::Descending = *order {
+        x.reverse();
+    }
+}
+
+// src を上から i 番目の桁で安定に整列して dst に書き込む。
+// すべての要素の桁が同じで、書き込む必要がなければ false を返す
+fn counting_sort<T: RadixKey>(src: &[T], dst: &mut [T], i: usize, chunk_len: usize) -> bool {
+    let counts: Vec<[usize; RADIX]> = src
+        .par_chunks(chunk_len)
+        .map(|chunk| {
+            let mut counts = [0; RADIX];
+            for v in chunk {
+                counts[v.digit(i)] += 1;
+            }
+            counts
+        })
+        .collect();
+
+    let mut offsets = counts.clone();
+    let mut next = 0;
+    for d in 0..RADIX {
+        let total: usize = counts.iter().map(|c| c[d]).sum();
+        if total == src.len() {
+            return false;
+        }
+        for (offset, count) in offsets.iter_mut().zip(&counts) {
+            offset[d] = next;
+            next += count[d];
+        }
+    }
+
+    let dst = SharedSlice(dst.as_mut_ptr());
+    src.par_chunks(chunk_len)
+        .zip(offsets)
+        .for_each(|(chunk, mut offset)| {
+            for &v in chunk {
+                let d = v.digit(i);
+                // SAFETY: offset は src.len() (= dst.len()) 未満で、チャンクごと、数字ごとに
+                // 重ならない区間を指すので、どの位置にも1度だけ書き込む
+                unsafe { dst.get().add(offset[d]).write(v) };
+                offset[d] += 1;
+            }
+        });
+    true
+}
+
+// 重ならない位置に並列に書き込むためのポインタ
+struct SharedSlice<T>(*mut T);
+
+// SAFETY: counting_sort で、各スレッドは互いに重ならない位置にだけ書き込む
+unsafe impl<T: Send> Send for SharedSlice<T> {}
+unsafe impl<T: Send> Sync for SharedSlice<T> {}
+
+impl<T> SharedSlice<T> {
+    // クロージャがフィールドではなく構造体ごと借用するように、メソッドで取り出す
+    fn get(&self) -> *mut T {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sort;
+    use crate::fourth::PARALLEL_THRESHOLD;
+    use crate::utils::new_u32_vec;
+    use crate::SortOrder::*;
+
+    #[test]
+    fn sort_unsigned_and_signed() {
+        let x = new_u32_vec(PARALLEL_THRESHOLD * 5 + 3);
+        let mut expected = x.clone();
+        expected.sort();
+        let mut y = x.clone();
+        sort(&mut y, &Ascending);
+        assert_eq!(y, expected);
+
+        let x: Vec<i64> = x
+            .iter()
+            .map(|&v| ((v as i64) << 16) - (1 << 40) + (v % 7) as i64)
+            .chain([i64::MIN, i64::MAX, 0, -1])
+            .collect();
+        let mut expected = x.clone();
+        expected.sort_by(|a, b| b.cmp(a));
+        let mut y = x.clone();
+        sort(&mut y, &Descending);
+        assert_eq!(y, expected);
+
+        let mut y: Vec<i8> = vec![3, -128, 127, 0, -1, 1];
+        sort(&mut y, &Ascending);
+        assert_eq!(y, [-128, -1, 0, 1, 3, 127]);
+    }
+
+    #[test]
+    fn sort_byte_strings_in_lexicographic_order() {
+        let words: Vec<String> = new_u32_vec(3000)
+            .into_iter()
+            .map(|v| format!("{:x}", v % 100000).repeat(v as usize % 3))
+            .collect();
+        let mut x: Vec<&str> = words.iter().map(|w| w.as_str()).collect();
+        x.extend(["", "a", "ab", "abc", "b", "\u{3042}"]);
+        let mut expected = x.clone();
+        expected.sort();
+        sort(&mut x, &Ascending);
+        assert_eq!(x, expected);
+
+        let mut x: Vec<&[u8]> = vec![b"ba", b"\xff", b"b", b"", b"a\x00", b"a"];
+        sort(&mut x, &Descending);
+        let expected: [&[u8]; 6] = [b"\xff", b"ba", b"b", b"a\x00", b"a", b""];
+        assert_eq!(x, expected);
+    }
+
+    #[test]
+    fn sort_short_and_uniform_inputs() {
+        let mut x: Vec<u16> = vec![];
+        sort(&mut x, &Ascending);
+        let mut x = vec![5u16];
+        sort(&mut x, &Descending);
+        assert_eq!(x, [5]);
+        let mut x = vec![42u32; 10000];
+        sort(&mut x, &Ascending);
+        assert_eq!(x, vec![42; 10000]);
+    }
+}
@@ -1,41 +1,34 @@
 use super::SortOrder;
 
-pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), String> {
-    if x.len().is_power_of_two() {
-        match *order {
-            SortOrder::Ascending => do_sort(x, true),
-            SortOrder::Descending => do_sort(x, false),
-        }
-        Ok(())
-    } else {
-        Err(format!(
-            "The length of x is not a power of two. (x.len(): {}",
-            x.len()
-        ))
+pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) {
+    match *order {
+        SortOrder::Ascending => do_sort(x, true),
+        SortOrder::Descending => do_sort(x, false),
     }
 }
 
 fn do_sort<T: Ord>(x: &mut [T], up: bool) {
     if x.len() > 1 {
+        // 前半を逆向き、後半を指定の向きに並べると、長さによらずバイトニック列になる
         let mid_point = x.len() / 2;
-        do_sort(&mut x[..mid_point], true);
-        do_sort(&mut x[mid_point..], false);
+        do_sort(&mut x[..mid_point], !up);
+        do_sort(&mut x[mid_point..], up);
         sub_sort(x, up);
     }
 }
 
 fn sub_sort<T: Ord>(x: &mut [T], up: bool) {
     if x.len() > 1 {
-        compare_and_swap(x, up);
-        let mid_point = x.len() / 2;
+        // x.len() 未満で最大の2のべき乗で区切る。長さが2のべき乗なら半分になる
+        let mid_point = x.len().next_power_of_two() / 2;
+        compare_and_swap(x, mid_point, up);
         sub_sort(&mut x[..mid_point], up);
         sub_sort(&mut x[mid_point..], up);
     }
 }
 
-fn compare_and_swap<T: Ord>(x: &mut [T], up: bool) {
-    let mid_point = x.len() / 2;
-    for i in 0..mid_point {
+fn compare_and_swap<T: Ord>(x: &mut [T], mid_point: usize, up: bool) {
+    for i in 0..x.len() - mid_point {
         if (x[i] > x[mid_point + i]) == up {
             x.swap(i, mid_point + i);
         }
@@ -45,19 +38,20 @@ fn compare_and_swap<T: Ord>(x: &mut [T], up: bool) {
 #[cfg(test)]
 mod tests {
     use super::sort;
+    use crate::utils::new_u32_vec;
     use crate::SortOrder::*;
 
     #[test]
     fn sort_u32_ascending() {
         let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
-        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+        sort(&mut x, &Ascending);
         assert_eq!(x, vec![4, 10, 11, 20, 21, 30, 110, 330]);
     }
 
     #[test]
     fn sort_u32_descending() {
         let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
-        assert_eq!(sort(&mut x, &Descending), Ok(()));
+        sort(&mut x, &Descending);
         assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
     }
 
@@ -73,7 +67,7 @@ mod tests {
             "no",
             "GC",
         ];
-        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+        sort(&mut x, &Ascending);
         assert_eq!(
             x,
             vec![
@@ -101,7 +95,7 @@ mod tests {
             "no",
             "GC",
         ];
-        assert_eq!(sort(&mut x, &Descending), Ok(()));
+        sort(&mut x, &Descending);
         assert_eq!(
             x,
             vec![
@@ -118,8 +112,23 @@ mod tests {
     }
 
     #[test]
-    fn sort_to_fail() {
+    fn sort_odd_length() {
         let mut x = vec![10, 30, 11];
-        assert!(sort(&mut x, &Ascending).is_err());
+        sort(&mut x, &Ascending);
+        assert_eq!(x, vec![10, 11, 30]);
+    }
+
+    #[test]
+    fn sort_u32_arbitrary_length() {
+        for len in 0..=130 {
+            let mut x = new_u32_vec(len);
+            let mut expected = x.clone();
+            expected.sort();
+            sort(&mut x, &Ascending);
+            assert_eq!(x, expected);
+            sort(&mut x, &Descending);
+            expected.reverse();
+            assert_eq!(x, expected);
+        }
     }
 }
@@ -0,0 +1,359 @@
+use super::fourth::{self, PARALLEL_THRESHOLD};
+use super::SortOrder;
+use rayon::prelude::*;
+use std::cmp::Ordering;
+
+// 列全体を整列せずに、小さい方から k 個の要素だけを求める
+//
+// 列を長さ b (k 以上の2のべき乗) のブロックに分けて並列に整列し、トーナメントのように
+// 2つずつブロックを合わせていく。昇順に並んだブロック a と c を合わせるときは、
+// a の大きい方から i 番目と c の小さい方から i 番目を比べて小さい方を a に残す。
+// すると a には2つのブロックの小さい方から b 個が、バイトニック列として残るので、
+// fourth の sub_sort で整列できる
+
+/// x の小さい方から k 個を x[..k] に整列して置く。残りの要素の順序は決まらない
+pub fn partial_sort_by<T, F>(x: &mut [T], k: usize, comparator: &F)
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    let k = k.min(x.len());
+    if k == 0 {
+        return;
+    }
+    let block = k.next_power_of_two();
+    if block >= x.len() {
+        fourth::sort_by(x, comparator);
+        return;
+    }
+
+    x.par_chunks_mut(block)
+        .for_each(|chunk| fourth::sort_by(chunk, comparator));
+
+    // 幅 group の区間ごとに、先頭のブロックと半分の位置にあるブロックを合わせる
+    let mut group = 2 * block;
+    while group / 2 < x.len() {
+        x.par_chunks_mut(group).for_each(|chunk| {
+            if chunk.len() > group / 2 {
+                let (first, rest) = chunk.split_at_mut(group / 2);
+                let partner = block.min(rest.len());
+                merge_smallest(&mut first[..block], &mut rest[..partner], comparator);
+            }
+        });
+        group *= 2;
+    }
+}
+
+pub fn partial_sort<T: Ord + Send>(x: &mut [T], k: usize, order: &SortOrder) {
+    match *order {
+        SortOrder::Ascending => partial_sort_by(x, k, &|a, b| a.cmp(b)),
+        SortOrder::Descending => partial_sort_by(x, k, &|a, b| b.cmp(a)),
+    }
+}
+
+/// x を変えずに、小さい方から k 個を整列した Vec で返す
+pub fn top_k_by<T, F>(x: &[T], k: usize, comparator: &F) -> Vec<T>
+where
+    T: Clone + Send + Sync,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    let k = k.min(x.len());
+    if k == 0 {
+        return Vec::new();
+    }
+    // 各チャンクの上位 k 個を求め、2つずつマージして上位 k 個を残す
+    let chunk_len = k.saturating_mul(4).max(PARALLEL_THRESHOLD);
+    x.par_chunks(chunk_len)
+        .map(|chunk| {
+            let mut chunk = chunk.to_vec();
+            partial_sort_by(&mut chunk, k, comparator);
+            chunk.truncate(k);
+            chunk
+        })
+        .reduce(Vec::new, |a, b| merge_sorted_prefix(a, b, k, comparator))
+}
+
+pub fn top_k<T: Ord + Clone + Send + Sync>(x: &[T], k: usize, order: &SortOrder) -> Vec<T> {
+    match *order {
+        SortOrder::Ascending => top_k_by(x, k, &|a, b| a.cmp(b)),
+        SortOrder::Descending => top_k_by(x, k, &|a, b| b.cmp(a)),
+    }
+}
+
+/// 整列したときに n 番目に来る要素を x[n] に置き、その参照を返す
+///
+/// x[..n] の要素は x[n] 以下、x[n + 1..] の要素は x[n] 以上になる。
+/// 3つの要素の中央値を軸にした quickselect で求め、長い区間の分割は並列に行う。
+/// 分割がうまく進まないときは、残りの区間を partial_sort_by で処理する
+pub fn select_nth_by<'a, T, F>(x: &'a mut [T], n: usize, comparator: &F) -> &'a mut T
+where
+    T: Send + Sync,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    assert!(
+        n < x.len(),
+        "index {} out of range for length {}",
+        n,
+        x.len()
+    );
+    let (mut lo, mut hi) = (0, x.len());
+    let mut rounds = 2 * (usize::BITS - x.len().leading_zeros());
+    while hi - lo > 1 {
+        let s = &mut x[lo..hi];
+        if rounds == 0 {
+            partial_sort_by(s, n - lo + 1, comparator);
+            break;
+        }
+        rounds -= 1;
+        let pivot = median_of_three(s, comparator);
+        let (lt, gt) = partition(s, pivot, comparator);
+        if n < lo + lt {
+            hi = lo + lt;
+        } else if n >= lo + gt {
+            lo += gt;
+        } else {
+            break;
+        }
+    }
+    &mut x[n]
+}
+
+pub fn select_nth<'a, T: Ord + Send + Sync>(
+    x: &'a mut [T],
+    n: usize,
+    order: &SortOrder,
+) -> &'a mut T {
+    match *order {
+        SortOrder::Ascending => select_nth_by(x, n, &|a, b| a.cmp(b)),
+        SortOrder::Descending => select_nth_by(x, n, &|a, b| b.cmp(a)),
+    }
+}
+
+// 先頭、中央、末尾の要素のうち中央値の位置を返す
+fn median_of_three<T, F>(x: &[T], comparator: &F) -> usize
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    let (a, b, c) = (0, x.len() / 2, x.len() - 1);
+    let less = |i: usize, j: usize| comparator(&x[i], &x[j]) == Ordering::Less;
+    if less(a, b) {
+        if less(b, c) {
+            b
+        } else if less(a, c) {
+            c
+        } else {
+            a
+        }
+    } else if less(a, c) {
+        a
+    } else if less(b, c) {
+        c
+    } else {
+        b
+    }
+}
+
+// x[pivot] を軸に x を3つに分ける。(lt, gt) を返し、
+// x[..lt] は軸より小さく、x[lt..gt] は軸と等しく、x[gt..] は軸より大きい
+fn partition<T, F>(x: &mut [T], pivot: usize, comparator: &F) -> (usize, usize)
+where
+    T: Send + Sync,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    x.swap(0, pivot);
+    let (p, rest) = x.split_at_mut(1);
+    let (lt, gt) = partition_around(rest, &p[0], comparator);
+    // 軸を小さい要素の直後に移す
+    x.swap(0, lt);
+    (lt, gt + 1)
+}
+
+// p を軸に x を3つに分ける。(lt, gt) の意味は partition と同じ
+//
+// 長い区間は半分ずつ並列に分け、[小 等 大][小 等 大] の中ほどを回転して
+// [小 小 等 等 大 大] にまとめる。比較は並列に行い、移動だけが区間の長さに比例する
+fn partition_around<T, F>(x: &mut [T], p: &T, comparator: &F) -> (usize, usize)
+where
+    T: Send + Sync,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    let mid = x.len() / 2;
+    if mid >= PARALLEL_THRESHOLD {
+        let (first, second) = x.split_at_mut(mid);
+        let ((lt1, gt1), (lt2, gt2)) = rayon::join(
+            || partition_around(first, p, comparator),
+            || partition_around(second, p, comparator),
+        );
+        // 後半の小さい要素を前半の等しい要素の前に、後半の等しい要素を前半の大きい要素の前に移す
+        x[lt1..mid + lt2].rotate_right(lt2);
+        let lt = lt1 + lt2;
+        let gt = lt + (gt1 - lt1) + (gt2 - lt2);
+        x[lt + (gt1 - lt1)..mid + gt2].rotate_right(gt2 - lt2);
+        return (lt, gt);
+    }
+
+    let (mut lt, mut i, mut gt) = (0, 0, x.len());
+    while i < gt {
+        match comparator(&x[i], p) {
+            Ordering::Less => {
+                x.swap(lt, i);
+                lt += 1;
+                i += 1;
+            }
+            Ordering::Greater => {
+                gt -= 1;
+                x.swap(i, gt);
+            }
+            Ordering::Equal => i += 1,
+        }
+    }
+    (lt, gt)
+}
+
+// 昇順の a (長さは2のべき乗) と c から小さい方 a.len() 個を a に整列して集める
+fn merge_smallest<T, F>(a: &mut [T], c: &mut [T], comparator: &F)
+where
+    T: Send,
+    F: Sync + Fn(&T, &T) -> Ordering,
+{
+    let last = a.len() - 1;
+    for (i, y) in c.iter_mut().enumerate() {
+        if comparator(y, &a[last - i]) == Ordering::Less {
+            std::mem::swap(y, &mut a[last - i]);
+        }
+    }
+    fourth::sub_sort(a, true, comparator, PARALLEL_THRESHOLD);
+}
+
+// 整列済みの a と b をマージし、先頭の k 個を返す
+fn merge_sorted_prefix<T, F>(a: Vec<T>, b: Vec<T>, k: usize, comparator: &F) -> Vec<T>
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    let mut merged = Vec::with_capacity(k.min(a.len() + b.len()));
+    let (mut a, mut b) = (a.into_iter().peekable(), b.into_iter().peekable());
+    while merged.len() < k {
+        let next = match (a.peek(), b.peek()) {
+            (Some(x), Some(y)) if comparator(y, x) == Ordering::Less => b.next(),
+            (Some(_), _) => a.next(),
+            (None, _) => b.next(),
+        };
+        match next {
+            Some(v) => merged.push(v),
+            None => break,
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{partial_sort, partial_sort_by, select_nth, select_nth_by, top_k};
+    use crate::fourth::PARALLEL_THRESHOLD;
+    use crate::utils::new_u32_vec;
+    use crate::SortOrder::*;
+
+    #[test]
+    fn partial_sort_matches_full_sort_prefix() {
+        for len in [0, 1, 7, 100, 1000, 10007] {
+            for k in [0, 1, 3, 16, 17, 500, len] {
+                let mut x = new_u32_vec(len);
+                let mut expected = x.clone();
+                expected.sort();
+                partial_sort(&mut x, k, &Ascending);
+                let k = k.min(len);
+                assert_eq!(x[..k], expected[..k], "len: {}, k: {}", len, k);
+                // 残りも元の要素のまま
+                x.sort();
+                assert_eq!(x, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn partial_sort_by_comparator() {
+        let mut x: Vec<(u32, u32)> = new_u32_vec(5000).into_iter().map(|v| (v % 50, v)).collect();
+        let mut expected = x.clone();
+        expected.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        partial_sort_by(&mut x, 123, &|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        assert_eq!(x[..123], expected[..123]);
+    }
+
+    #[test]
+    fn top_k_keeps_input() {
+        let x = new_u32_vec(20000);
+        let mut expected = x.clone();
+        expected.sort_by(|a, b| b.cmp(a));
+        for k in [0, 1, 10, 5000, 30000, usize::MAX / 2, usize::MAX] {
+            let top = top_k(&x, k, &Descending);
+            assert_eq!(top, expected[..k.min(x.len())]);
+        }
+        assert_eq!(x, new_u32_vec(20000));
+    }
+
+    #[test]
+    fn select_nth_partitions() {
+        for len in [1, 2, 9, 1000, 4099] {
+            let x = new_u32_vec(len);
+            let mut expected = x.clone();
+            expected.sort();
+            for n in [0, len / 3, len / 2, len - 1] {
+                let mut y = x.clone();
+                assert_eq!(*select_nth(&mut y, n, &Ascending), expected[n]);
+                assert!(y[..n].iter().all(|v| *v <= y[n]));
+                assert!(y[n + 1..].iter().all(|v| *v >= y[n]));
+            }
+        }
+        let mut x = new_u32_vec(999);
+        let median = *select_nth_by(&mut x, 499, &|a, b| a.cmp(b));
+        let mut sorted = new_u32_vec(999);
+        sorted.sort();
+        assert_eq!(median, sorted[499]);
+    }
+
+    #[test]
+    fn select_nth_with_sorted_and_duplicate_inputs() {
+        let inputs = [
+            (0..5000).collect::<Vec<u32>>(),
+            (0..5000).rev().collect(),
+            new_u32_vec(5000).into_iter().map(|v| v % 3).collect(),
+            vec![7; 5000],
+        ];
+        for x in inputs {
+            let mut expected = x.clone();
+            expected.sort_by(|a, b| b.cmp(a));
+            for n in [0, 1, 2500, 4999] {
+                let mut y = x.clone();
+                assert_eq!(*select_nth(&mut y, n, &Descending), expected[n]);
+            }
+        }
+    }
+
+    #[test]
+    fn select_nth_partitions_long_ranges_in_parallel() {
+        let len = PARALLEL_THRESHOLD * 9 + 5;
+        let inputs = [
+            new_u32_vec(len),
+            new_u32_vec(len).into_iter().map(|v| v % 17).collect(),
+            (0..len as u32).rev().collect::<Vec<u32>>(),
+        ];
+        for x in inputs {
+            let mut expected = x.clone();
+            expected.sort();
+            for n in [0, len / 4, len / 2, len - 1] {
+                let mut y = x.clone();
+                assert_eq!(*select_nth(&mut y, n, &Ascending), expected[n]);
+                assert!(y[..n].iter().all(|v| *v <= y[n]));
+                assert!(y[n + 1..].iter().all(|v| *v >= y[n]));
+                y.sort();
+                assert_eq!(y, expected);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn select_nth_out_of_range() {
+        select_nth(&mut [1, 2, 3], 3, &Ascending);
+    }
+}
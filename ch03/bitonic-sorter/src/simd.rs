@@ -0,0 +1,304 @@
+use super::{fourth, third, SortOrder};
+use std::cmp::{max, min};
+
+// u32、i32、f32、u64 専用のバイトニックソート
+//
+// compare_and_swap を分岐のない min/max の繰り返しにすることで、ループがベクトル命令に
+// 変換されるようにしている。小さなブロックは固定長の配列に移してから整列するので、
+// レジスタの上だけで処理できる。x86/x86_64 では実行時に AVX2 が使えるか調べ、使えれば
+// この2つ (min_max と sort_network) を AVX2 を有効にしてコンパイルした版で整列する。
+// 分割や rayon の呼び出しは通常のコードのまま。AVX2 が使えなければ third と fourth の
+// 汎用の実装に任せる
+
+/// SIMD 版のソートで扱えるキーの型
+pub trait SimdKey: Copy + Send + Sync {
+    #[doc(hidden)]
+    fn sort_keys(x: &mut [Self], up: bool, parallel: bool);
+}
+
+pub fn sort<T: SimdKey>(x: &mut [T], order: &SortOrder) {
+    T::sort_keys(x, matches!(*order, SortOrder::Ascending), false);
+}
+
+pub fn par_sort<T: SimdKey>(x: &mut [T], order: &SortOrder) {
+    T::sort_keys(x, matches!(*order, SortOrder::Ascending), true);
+}
+
+// ベクトルの1レーンに載せる整数。MIN と MAX はブロックを埋める番兵に使う
+trait Lane: Copy + Ord + Send + Sync {
+    const MIN: Self;
+    const MAX: Self;
+}
+
+macro_rules! impl_lane {
+    ($($t:ty),*) => {$(
+        impl Lane for $t {
+            const MIN: Self = <$t>::MIN;
+            const MAX: Self = <$t>::MAX;
+        }
+
+        impl SimdKey for $t {
+            fn sort_keys(x: &mut [Self], up: bool, parallel: bool) {
+                sort_lanes(x, up, parallel);
+            }
+        }
+    )*};
+}
+
+impl_lane!(u32, i32, u64);
+
+// f32 はビット列を、IEEE 754 の全順序と同じ順に並ぶ i32 に変換して整列する
+impl SimdKey for f32 {
+    fn sort_keys(x: &mut [Self], up: bool, parallel: bool) {
+        // SAFETY: f32 と i32 は大きさもアラインメントも同じで、どんなビット列も有効な値になる
+        let keys: &mut [i32] =
+            unsafe { std::slice::from_raw_parts_mut(x.as_mut_ptr().cast(), x.len()) };
+        // 負の数は符号以外のビットを反転すると、絶対値が大きいほど小さくなる。
+        // この変換は2回行うと元に戻る
+        let flip = |k: &mut i32| *k ^= (((*k >> 31) as u32) >> 1) as i32;
+        keys.iter_mut().for_each(flip);
+        sort_lanes(keys, up, parallel);
+        keys.iter_mut().for_each(flip);
+    }
+}
+
+const PARALLEL_THRESHOLD: usize = 4096;
+
+// この長さ以下の列はレジスタに載る固定長の配列で整列する
+const BLOCK: usize = 16;
+
+fn sort_lanes<T: Lane>(x: &mut [T], up: bool, parallel: bool) {
+    if has_avx2() {
+        do_sort(x, up, parallel);
+    } else {
+        sort_generic(x, up, parallel);
+    }
+}
+
+fn sort_generic<T: Lane>(x: &mut [T], up: bool, parallel: bool) {
+    let comparator = |a: &T, b: &T| if up { a.cmp(b) } else { b.cmp(a) };
+    if parallel {
+        fourth::sort_by(x, &comparator);
+    } else {
+        third::sort_by(x, &comparator);
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn has_avx2() -> bool {
+    is_x86_feature_detected!("avx2")
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn has_avx2() -> bool {
+    false
+}
+
+// AVX2 が使えるときだけ呼ぶ。compare_and_swap と sort_block はそれを前提に AVX2 の命令を使う
+fn do_sort<T: Lane>(x: &mut [T], up: bool, parallel: bool) {
+    if x.len() <= BLOCK {
+        sort_block(x, up);
+        return;
+    }
+    let mid_point = x.len() / 2;
+    let (first, second) = x.split_at_mut(mid_point);
+    if parallel && mid_point >= PARALLEL_THRESHOLD {
+        rayon::join(
+            || do_sort(first, !up, parallel),
+            || do_sort(second, up, parallel),
+        );
+    } else {
+        do_sort(first, !up, parallel);
+        do_sort(second, up, parallel);
+    }
+    sub_sort(x, up, parallel);
+}
+
+fn sub_sort<T: Lane>(x: &mut [T], up: bool, parallel: bool) {
+    if x.len() <= BLOCK {
+        sort_block(x, up);
+        return;
+    }
+    let mid_point = x.len().next_power_of_two() / 2;
+    compare_and_swap(x, mid_point, up);
+    let (first, second) = x.split_at_mut(mid_point);
+    if parallel && mid_point >= PARALLEL_THRESHOLD {
+        rayon::join(
+            || sub_sort(first, up, parallel),
+            || sub_sort(second, up, parallel),
+        );
+    } else {
+        sub_sort(first, up, parallel);
+        sub_sort(second, up, parallel);
+    }
+}
+
+fn compare_and_swap<T: Lane>(x: &mut [T], mid_point: usize, up: bool) {
+    let (first, second) = x.split_at_mut(mid_point);
+    let first = &mut first[..second.len()];
+    // 降順のときは前半と後半の役割を入れ替える
+    let (lo, hi) = if up { (first, second) } else { (second, first) };
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        // SAFETY: sort_lanes で AVX2 が使えることを確かめてある
+        unsafe { min_max_avx2(lo, hi) };
+    }
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    min_max(lo, hi);
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn min_max_avx2<T: Lane>(lo: &mut [T], hi: &mut [T]) {
+    min_max(lo, hi);
+}
+
+// lo[i] と hi[i] のうち小さい方を lo[i] に、大きい方を hi[i] に置く
+#[inline(always)]
+fn min_max<T: Lane>(lo: &mut [T], hi: &mut [T]) {
+    for (a, b) in lo.iter_mut().zip(hi.iter_mut()) {
+        let (x, y) = (*a, *b);
+        *a = min(x, y);
+        *b = max(x, y);
+    }
+}
+
+fn sort_block<T: Lane>(x: &mut [T], up: bool) {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        // SAFETY: sort_lanes で AVX2 が使えることを確かめてある
+        unsafe { sort_network_avx2(x, up) };
+    }
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    sort_network(x, up);
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn sort_network_avx2<T: Lane>(x: &mut [T], up: bool) {
+    sort_network(x, up);
+}
+
+// 長さ BLOCK の配列に移し、余りを番兵で埋めてから整列する。
+// 昇順なら MAX、降順なら MIN で埋めるので、番兵は末尾に集まる
+#[inline(always)]
+fn sort_network<T: Lane>(x: &mut [T], up: bool) {
+    let mut block = [if up { T::MAX } else { T::MIN }; BLOCK];
+    block[..x.len()].copy_from_slice(x);
+    let mut k = 2;
+    while k <= BLOCK {
+        let mut j = k / 2;
+        while j > 0 {
+            for i in 0..BLOCK {
+                let l = i ^ j;
+                if l > i {
+                    let (a, b) = (block[i], block[l]);
+                    let (lo, hi) = (min(a, b), max(a, b));
+                    if ((i & k) == 0) == up {
+                        block[i] = lo;
+                        block[l] = hi;
+                    } else {
+                        block[i] = hi;
+                        block[l] = lo;
+                    }
+                }
+            }
+            j /= 2;
+        }
+        k *= 2;
+    }
+    x.copy_from_slice(&block[..x.len()]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{par_sort, sort, sort_generic};
+    use crate::utils::new_u32_vec;
+    use crate::SortOrder::*;
+
+    #[test]
+    fn sort_u32_matches_std() {
+        for len in (0..=200).chain([4099, 65536, 65537]) {
+            let mut x = new_u32_vec(len);
+            let mut expected = x.clone();
+            expected.sort();
+            let mut y = x.clone();
+            sort(&mut x, &Ascending);
+            par_sort(&mut y, &Ascending);
+            assert_eq!(x, expected);
+            assert_eq!(y, expected);
+            expected.reverse();
+            sort(&mut x, &Descending);
+            par_sort(&mut y, &Descending);
+            assert_eq!(x, expected);
+            assert_eq!(y, expected);
+        }
+    }
+
+    #[test]
+    fn sort_i32_and_u64_match_std() {
+        for len in (0..=100).chain([10007]) {
+            let mut x: Vec<i32> = new_u32_vec(len).into_iter().map(|n| n as i32).collect();
+            let mut expected = x.clone();
+            expected.sort();
+            par_sort(&mut x, &Ascending);
+            assert_eq!(x, expected);
+
+            let mut x: Vec<u64> = new_u32_vec(len)
+                .into_iter()
+                .map(|n| (n as u64) << 32 | n.rotate_left(7) as u64)
+                .collect();
+            let mut expected = x.clone();
+            expected.sort_by(|a, b| b.cmp(a));
+            par_sort(&mut x, &Descending);
+            assert_eq!(x, expected);
+        }
+    }
+
+    #[test]
+    fn generic_fallback_matches_std() {
+        for len in [0, 1, 17, 1000, 10007] {
+            let x = new_u32_vec(len);
+            let mut expected = x.clone();
+            expected.sort();
+            for parallel in [false, true] {
+                let mut y = x.clone();
+                sort_generic(&mut y, true, parallel);
+                assert_eq!(y, expected);
+                sort_generic(&mut y, false, parallel);
+                assert!(y.iter().eq(expected.iter().rev()));
+            }
+        }
+    }
+
+    #[test]
+    fn sort_f32_in_total_order() {
+        let mut x = vec![
+            1.5,
+            f32::NAN,
+            -0.0,
+            f32::NEG_INFINITY,
+            0.0,
+            -f32::NAN,
+            -2.5,
+            f32::INFINITY,
+            f32::MIN_POSITIVE,
+            -1.5,
+        ];
+        let mut expected = x.clone();
+        expected.sort_by(|a, b| a.total_cmp(b));
+        sort(&mut x, &Ascending);
+        let bits = |v: &[f32]| v.iter().map(|f| f.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&x), bits(&expected));
+
+        let mut x: Vec<f32> = new_u32_vec(5000)
+            .into_iter()
+            .map(|n| (n as i32) as f32 / 1024.0)
+            .collect();
+        let mut expected = x.clone();
+        expected.sort_by(|a, b| b.total_cmp(a));
+        par_sort(&mut x, &Descending);
+        assert_eq!(bits(&x), bits(&expected));
+    }
+}
@@ -1,37 +1,119 @@
 use std::cmp::Ordering;
 
+use super::float::{self, Float, NanError, NanPolicy};
+use super::utils::apply_sorted_indices;
+pub use super::utils::{apply_permutation, invert_permutation};
 use super::SortOrder;
 
-pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), String>
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F)
 where
     F: Fn(&T, &T) -> Ordering,
 {
-    if x.len().is_power_of_two() {
-        do_sort(x, true, comparator);
-        Ok(())
-    } else {
-        Err(format!(
-            "The length of x is not a power of two. (x.len(): {}",
-            x.len()
-        ))
-    }
+    do_sort(x, true, comparator);
 }
 
-pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), String> {
+pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) {
     match *order {
         SortOrder::Ascending => sort_by(x, &|a, b| a.cmp(b)),
         SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
     }
 }
 
+pub fn sort_by_key<T, K, F>(x: &mut [T], key: &F)
+where
+    K: Ord,
+    F: Fn(&T) -> K,
+{
+    sort_by(x, &|a, b| key(a).cmp(&key(b)));
+}
+
+// キーは各要素につき1回だけ計算する
+pub fn sort_by_cached_key<T, K, F>(x: &mut [T], key: &F)
+where
+    K: Ord,
+    F: Fn(&T) -> K,
+{
+    let mut keyed: Vec<(K, usize)> = x.iter().map(key).enumerate().map(|(i, k)| (k, i)).collect();
+    sort_by(&mut keyed, &|a, b| a.0.cmp(&b.0));
+    let mut indices: Vec<_> = keyed.into_iter().map(|(_, i)| i).collect();
+    apply_sorted_indices(x, &mut indices);
+}
+
+// 要素に元の位置を添えて比較するので、等しい要素の順序が保たれる
+pub fn stable_sort_by<T, F>(x: &mut [T], comparator: &F)
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    let mut indices = argsort_by(x, comparator);
+    apply_sorted_indices(x, &mut indices);
+}
+
+pub fn stable_sort<T: Ord>(x: &mut [T], order: &SortOrder) {
+    match *order {
+        SortOrder::Ascending => stable_sort_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => stable_sort_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+/// f32/f64 を IEEE 754 の全順序で整列する。-0.0 は 0.0 より前に来る
+///
+/// NaN は nan に従って扱う。エラーのときは x を変更しない
+pub fn sort_floats<T: Float>(
+    x: &mut [T],
+    order: &SortOrder,
+    nan: NanPolicy,
+) -> Result<(), NanError> {
+    float::sort_keys_with(x, nan, |keys| sort(keys, order))
+}
+
+/// x を整列したときに i 番目に来る要素の元の位置を返す。等しい要素は元の順に並ぶ
+pub fn argsort_by<T, F>(x: &[T], comparator: &F) -> Vec<usize>
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    let mut indices: Vec<usize> = (0..x.len()).collect();
+    sort_by(&mut indices, &|&a, &b| {
+        comparator(&x[a], &x[b]).then(a.cmp(&b))
+    });
+    indices
+}
+
+pub fn argsort<T: Ord>(x: &[T], order: &SortOrder) -> Vec<usize> {
+    match *order {
+        SortOrder::Ascending => argsort_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => argsort_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+/// keys を整列し、values の要素も keys の要素と同じ位置へ動かす
+pub fn sort_pairs_by<K, V, F>(keys: &mut [K], values: &mut [V], comparator: &F)
+where
+    F: Fn(&K, &K) -> Ordering,
+{
+    assert_eq!(
+        keys.len(),
+        values.len(),
+        "keys and values must have the same length"
+    );
+    do_sort_pairs(keys, values, true, comparator);
+}
+
+pub fn sort_pairs<K: Ord, V>(keys: &mut [K], values: &mut [V], order: &SortOrder) {
+    match *order {
+        SortOrder::Ascending => sort_pairs_by(keys, values, &|a, b| a.cmp(b)),
+        SortOrder::Descending => sort_pairs_by(keys, values, &|a, b| b.cmp(a)),
+    }
+}
+
 fn do_sort<T, F>(x: &mut [T], forward: bool, comparetor: &F)
 where
     F: Fn(&T, &T) -> Ordering,
 {
     if x.len() > 1 {
+        // 前半を逆向き、後半を指定の向きに並べると、長さによらずバイトニック列になる
         let mid_point = x.len() / 2;
-        do_sort(&mut x[..mid_point], true, comparetor);
-        do_sort(&mut x[mid_point..], false, comparetor);
+        do_sort(&mut x[..mid_point], !forward, comparetor);
+        do_sort(&mut x[mid_point..], forward, comparetor);
         sub_sort(x, forward, comparetor);
     }
 }
@@ -41,14 +123,15 @@ where
     F: Fn(&T, &T) -> Ordering,
 {
     if x.len() > 1 {
-        compare_and_swap(x, fowrard, comparator);
-        let mid_point = x.len() / 2;
+        // x.len() 未満で最大の2のべき乗で区切る。長さが2のべき乗なら半分になる
+        let mid_point = x.len().next_power_of_two() / 2;
+        compare_and_swap(x, mid_point, fowrard, comparator);
         sub_sort(&mut x[..mid_point], fowrard, comparator);
         sub_sort(&mut x[mid_point..], fowrard, comparator);
     }
 }
 
-fn compare_and_swap<T, F>(x: &mut [T], forward: bool, comparator: &F)
+fn compare_and_swap<T, F>(x: &mut [T], mid_point: usize, forward: bool, comparator: &F)
 where
     F: Fn(&T, &T) -> Ordering,
 {
@@ -57,45 +140,92 @@ where
     } else {
         Ordering::Less
     };
-    let mid_point = x.len() / 2;
-    for i in 0..mid_point {
+    for i in 0..x.len() - mid_point {
         if (comparator(&x[i], &x[mid_point + i])) == swap_condition {
             x.swap(i, mid_point + i);
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::sort;
-    use super::sort_by;
-    use crate::utils::{is_sorted_ascending, is_sorted_descending, new_u32_vec};
-    use crate::SortOrder::*;
+fn do_sort_pairs<K, V, F>(keys: &mut [K], values: &mut [V], forward: bool, comparator: &F)
+where
+    F: Fn(&K, &K) -> Ordering,
+{
+    if keys.len() > 1 {
+        let mid_point = keys.len() / 2;
+        do_sort_pairs(
+            &mut keys[..mid_point],
+            &mut values[..mid_point],
+            !forward,
+            comparator,
+        );
+        do_sort_pairs(
+            &mut keys[mid_point..],
+            &mut values[mid_point..],
+            forward,
+            comparator,
+        );
+        sub_sort_pairs(keys, values, forward, comparator);
+    }
+}
 
-    #[derive(Debug, PartialEq)]
-    struct Student {
-        first_name: String,
-        last_name: String,
-        age: u8,
+fn sub_sort_pairs<K, V, F>(keys: &mut [K], values: &mut [V], forward: bool, comparator: &F)
+where
+    F: Fn(&K, &K) -> Ordering,
+{
+    if keys.len() > 1 {
+        let mid_point = keys.len().next_power_of_two() / 2;
+        compare_and_swap_pairs(keys, values, mid_point, forward, comparator);
+        sub_sort_pairs(
+            &mut keys[..mid_point],
+            &mut values[..mid_point],
+            forward,
+            comparator,
+        );
+        sub_sort_pairs(
+            &mut keys[mid_point..],
+            &mut values[mid_point..],
+            forward,
+            comparator,
+        );
     }
+}
 
-    impl Student {
-        fn new(first_name: &str, last_name: &str, age: u8) -> Student {
-            Student {
-                first_name: first_name.to_string(),
-                last_name: last_name.to_string(),
-                age,
-            }
+// keys の要素を交換するときに、values の同じ位置の要素も交換する
+fn compare_and_swap_pairs<K, V, F>(
+    keys: &mut [K],
+    values: &mut [V],
+    mid_point: usize,
+    forward: bool,
+    comparator: &F,
+) where
+    F: Fn(&K, &K) -> Ordering,
+{
+    let swap_condition = if forward {
+        Ordering::Greater
+    } else {
+        Ordering::Less
+    };
+    for i in 0..keys.len() - mid_point {
+        if (comparator(&keys[i], &keys[mid_point + i])) == swap_condition {
+            keys.swap(i, mid_point + i);
+            values.swap(i, mid_point + i);
         }
     }
+}
 
-    // impl PartialEq for Student {
-    //     fn eq(&self, other: &Self) -> bool {
-    //         self.first_name == other.first_name
-    //             && self.last_name == other.last_name
-    //             && self.age == other.age
-    //     }
-    // }
+#[cfg(test)]
+mod tests {
+    use super::sort;
+    use super::sort_by;
+    use super::{apply_permutation, argsort, argsort_by, invert_permutation};
+    use super::{sort_by_cached_key, sort_by_key};
+    use super::{sort_floats, sort_pairs, sort_pairs_by};
+    use super::{stable_sort, stable_sort_by};
+    use crate::float::{NanError, NanPolicy};
+    use crate::utils::fixtures::{students, Student};
+    use crate::utils::{is_sorted_ascending, is_sorted_descending, new_u32_vec};
+    use crate::SortOrder::*;
 
     #[test]
     fn sort_students_by_age_ascending() {
@@ -105,7 +235,7 @@ mod tests {
         let ryosuke = Student::new("Ryosuke", "Hayashi", 17);
         let mut x = vec![&taro, &hanako, &kyoko, &ryosuke];
         let expected = vec![&hanako, &kyoko, &taro, &ryosuke];
-        assert_eq!(sort_by(&mut x, &|a, b| a.age.cmp(&b.age)), Ok(()));
+        sort_by(&mut x, &|a, b| a.age.cmp(&b.age));
         assert_eq!(x, expected);
     }
 
@@ -117,27 +247,83 @@ mod tests {
         let ryosuke = Student::new("Ryosuke", "Hayashi", 17);
         let mut x = vec![&taro, &hanako, &kyoko, &ryosuke];
         let expected = vec![&ryosuke, &kyoko, &hanako, &taro];
-        assert_eq!(
-            sort_by(&mut x, &|a, b| a
-                .last_name
+        sort_by(&mut x, &|a, b| {
+            a.last_name
                 .cmp(&b.last_name)
-                .then_with(|| a.first_name.cmp(&b.first_name))),
-            Ok(())
-        );
+                .then_with(|| a.first_name.cmp(&b.first_name))
+        });
+        assert_eq!(x, expected);
+    }
+
+    #[test]
+    fn sort_students_by_key() {
+        let [taro, hanako, kyoko, ryosuke] = students();
+        let mut x = vec![&taro, &hanako, &kyoko, &ryosuke];
+        sort_by_key(&mut x, &|s| s.age);
+        assert_eq!(x, vec![&hanako, &kyoko, &taro, &ryosuke]);
+        sort_by_key(&mut x, &|s| (s.last_name.clone(), s.first_name.clone()));
+        assert_eq!(x, vec![&ryosuke, &kyoko, &hanako, &taro]);
+    }
+
+    #[test]
+    fn sort_by_cached_key_computes_each_key_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let calls = AtomicUsize::new(0);
+        let mut x = new_u32_vec(1000);
+        let mut expected = x.clone();
+        expected.sort_by_key(|n| n.to_string());
+        sort_by_cached_key(&mut x, &|n| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            n.to_string()
+        });
         assert_eq!(x, expected);
+        assert_eq!(calls.load(Ordering::Relaxed), 1000);
+    }
+
+    #[test]
+    fn stable_sort_students_keeps_previous_order() {
+        let taro = Student::new("Taro", "Yamada", 16);
+        let hanako = Student::new("Hanako", "Yamada", 14);
+        let kyoko = Student::new("Kyoko", "Ito", 15);
+        let ryosuke = Student::new("Ryosuke", "Hayashi", 17);
+        let jiro = Student::new("Jiro", "Ito", 16);
+        let yuko = Student::new("Yuko", "Hayashi", 15);
+        let mut x = vec![&taro, &hanako, &kyoko, &ryosuke, &jiro, &yuko];
+
+        // 名字で並べてから年齢で並べると、同じ年齢の中では名字の順が保たれる
+        stable_sort_by(&mut x, &|a, b| a.last_name.cmp(&b.last_name));
+        assert_eq!(x, vec![&ryosuke, &yuko, &kyoko, &jiro, &taro, &hanako]);
+        stable_sort_by(&mut x, &|a, b| a.age.cmp(&b.age));
+        assert_eq!(x, vec![&hanako, &yuko, &kyoko, &jiro, &taro, &ryosuke]);
+    }
+
+    #[test]
+    fn stable_sort_u32_by_equal_keys() {
+        // 上位ビットだけで比較し、下位ビットに元の位置を入れておく
+        let mut x: Vec<u32> = new_u32_vec(1000)
+            .into_iter()
+            .enumerate()
+            .map(|(i, n)| (n & 0xf000_0000) | i as u32)
+            .collect();
+        stable_sort_by(&mut x, &|a, b| (a >> 28).cmp(&(b >> 28)));
+        assert!(is_sorted_ascending(&x));
+
+        let mut y = vec![3, 1, 2, 1];
+        stable_sort(&mut y, &Descending);
+        assert_eq!(y, vec![3, 2, 1, 1]);
     }
 
     #[test]
     fn sort_u32_ascending() {
         let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
-        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+        sort(&mut x, &Ascending);
         assert_eq!(x, vec![4, 10, 11, 20, 21, 30, 110, 330]);
     }
 
     #[test]
     fn sort_u32_descending() {
         let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
-        assert_eq!(sort(&mut x, &Descending), Ok(()));
+        sort(&mut x, &Descending);
         assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
     }
 
@@ -153,7 +339,7 @@ mod tests {
             "no",
             "GC",
         ];
-        assert_eq!(sort(&mut x, &Ascending), Ok(()));
+        sort(&mut x, &Ascending);
         assert_eq!(
             x,
             vec![
@@ -181,7 +367,7 @@ mod tests {
             "no",
             "GC",
         ];
-        assert_eq!(sort(&mut x, &Descending), Ok(()));
+        sort(&mut x, &Descending);
         assert_eq!(
             x,
             vec![
@@ -198,22 +384,144 @@ mod tests {
     }
 
     #[test]
-    fn sort_to_fail() {
+    fn sort_odd_length() {
         let mut x = vec![10, 30, 11];
-        assert!(sort(&mut x, &Ascending).is_err());
+        sort(&mut x, &Ascending);
+        assert_eq!(x, vec![10, 11, 30]);
+    }
+
+    #[test]
+    fn sort_u32_arbitrary_length() {
+        for len in 0..=130 {
+            let mut x = new_u32_vec(len);
+            let mut expected = x.clone();
+            expected.sort();
+            sort(&mut x, &Ascending);
+            assert_eq!(x, expected);
+            sort(&mut x, &Descending);
+            expected.reverse();
+            assert_eq!(x, expected);
+        }
+    }
+
+    #[test]
+    fn sort_zero_one_sequences() {
+        // 0-1 原理: 0 と 1 だけの列をすべて整列できれば、ソーティングネットワークは正しい
+        for len in 0..=12 {
+            for bits in 0..(1u32 << len) {
+                let mut x: Vec<u32> = (0..len).map(|i| (bits >> i) & 1).collect();
+                sort(&mut x, &Ascending);
+                assert!(is_sorted_ascending(&x), "{:?}", x);
+            }
+        }
     }
 
     #[test]
     fn sort_u32_large() {
         {
             let mut x = new_u32_vec(65536);
-            assert_eq!(sort(&mut x, &Ascending), Ok(()));
+            sort(&mut x, &Ascending);
             assert!(is_sorted_ascending(&x));
         }
         {
             let mut x = new_u32_vec(65536);
-            assert_eq!(sort(&mut x, &Descending), Ok(()));
+            sort(&mut x, &Descending);
             assert!(is_sorted_descending(&x));
         }
     }
+
+    #[test]
+    fn argsort_and_apply_permutation_to_columns() {
+        let keys: Vec<u32> = new_u32_vec(1000).into_iter().map(|v| v % 100).collect();
+        let names: Vec<String> = (0..keys.len()).map(|i| format!("row{}", i)).collect();
+
+        let perm = argsort(&keys, &Descending);
+        let mut expected: Vec<usize> = (0..keys.len()).collect();
+        expected.sort_by(|&a, &b| keys[b].cmp(&keys[a]));
+        assert_eq!(perm, expected);
+
+        // 同じ並べ替えを複数の列に適用する
+        let (mut k, mut n) = (keys.clone(), names.clone());
+        apply_permutation(&mut k, &perm);
+        apply_permutation(&mut n, &perm);
+        assert!(is_sorted_descending(&k));
+        for (key, name) in k.iter().zip(&n) {
+            let row: usize = name[3..].parse().unwrap();
+            assert_eq!(keys[row], *key);
+        }
+
+        // 逆の並べ替えで元に戻る
+        apply_permutation(&mut n, &invert_permutation(&perm));
+        assert_eq!(n, names);
+        assert_eq!(
+            argsort_by(&keys, &|a, b| a.cmp(b)),
+            argsort(&keys, &Ascending)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn apply_invalid_permutation() {
+        apply_permutation(&mut [1, 2, 3], &[0, 0, 1]);
+    }
+
+    #[test]
+    fn sort_pairs_moves_values_with_keys() {
+        let keys: Vec<u32> = new_u32_vec(1000).into_iter().map(|v| v % 50).collect();
+        let mut k = keys.clone();
+        let mut v: Vec<usize> = (0..keys.len()).collect();
+        sort_pairs(&mut k, &mut v, &Ascending);
+        assert!(is_sorted_ascending(&k));
+        assert!(v.iter().zip(&k).all(|(&i, key)| keys[i] == *key));
+
+        // 同じネットワークを通るので、組にして sort_by したときと同じ結果になる
+        let mut pairs: Vec<(u32, usize)> = keys.iter().copied().zip(0..).collect();
+        sort_by(&mut pairs, &|a, b| b.0.cmp(&a.0));
+        let (mut k, mut v) = (keys.clone(), (0..keys.len()).collect::<Vec<_>>());
+        sort_pairs_by(&mut k, &mut v, &|a, b| b.cmp(a));
+        assert_eq!(pairs, k.into_iter().zip(v).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sort_floats_with_nan_policy() {
+        let mut x: Vec<f64> = new_u32_vec(1000)
+            .into_iter()
+            .map(|v| (v as i32) as f64 / 7.0)
+            .collect();
+        x.extend([
+            f64::NAN,
+            -0.0,
+            0.0,
+            f64::INFINITY,
+            -f64::NAN,
+            f64::NEG_INFINITY,
+        ]);
+        let bits = |v: &[f64]| v.iter().map(|f| f.to_bits()).collect::<Vec<_>>();
+        let mut numbers: Vec<f64> = x.iter().copied().filter(|v| !v.is_nan()).collect();
+        numbers.sort_by(|a, b| a.total_cmp(b));
+
+        let mut y = x.clone();
+        sort_floats(&mut y, &Ascending, NanPolicy::Last).unwrap();
+        assert_eq!(bits(&y[..numbers.len()]), bits(&numbers));
+        assert!(y[numbers.len()..].iter().all(|v| v.is_nan()));
+
+        let mut y = x.clone();
+        sort_floats(&mut y, &Descending, NanPolicy::First).unwrap();
+        assert!(y[..2].iter().all(|v| v.is_nan()));
+        numbers.reverse();
+        assert_eq!(bits(&y[2..]), bits(&numbers));
+
+        let mut y = x.clone();
+        let index = x.len() - 6;
+        assert_eq!(
+            sort_floats(&mut y, &Ascending, NanPolicy::Error),
+            Err(NanError { index })
+        );
+        assert_eq!(bits(&y), bits(&x));
+
+        let mut y = vec![2.5f32, -1.0, -0.0, 0.0];
+        sort_floats(&mut y, &Ascending, NanPolicy::Error).unwrap();
+        assert_eq!(y, [-1.0, -0.0, 0.0, 2.5]);
+        assert!(y[1].is_sign_negative());
+    }
 }
@@ -14,3 +14,227 @@ pub fn is_sorted_ascending<T: Ord>(x: &[T]) -> bool {
 pub fn is_sorted_descending<T: Ord>(x: &[T]) -> bool {
     x.windows(2).all(|pair| pair[0] >= pair[1])
 }
+
+/// perm が 0..perm.len() の並べ替えになっているか調べる
+pub fn is_permutation(perm: &[usize]) -> bool {
+    let mut seen = vec![false; perm.len()];
+    perm.iter()
+        .all(|&p| p < perm.len() && !std::mem::replace(&mut seen[p], true))
+}
+
+/// x[i] に元の x[perm[i]] を置く。argsort の結果を渡すと x が整列される
+pub fn apply_permutation<T>(x: &mut [T], perm: &[usize]) {
+    assert!(
+        perm.len() == x.len() && is_permutation(perm),
+        "perm must be a permutation of 0..x.len()"
+    );
+    apply_sorted_indices(x, &mut perm.to_vec());
+}
+
+/// perm の逆の並べ替えを返す。元の位置 i の要素は、perm で並べ替えると inverse[i] に来る
+pub fn invert_permutation(perm: &[usize]) -> Vec<usize> {
+    assert!(
+        is_permutation(perm),
+        "perm must be a permutation of 0..perm.len()"
+    );
+    let mut inverse = vec![0; perm.len()];
+    for (i, &p) in perm.iter().enumerate() {
+        inverse[p] = i;
+    }
+    inverse
+}
+
+// x[indices[i]] にある要素を x[i] に移す。indices は書き換えられる
+pub(crate) fn apply_sorted_indices<T>(x: &mut [T], indices: &mut [usize]) {
+    for i in 0..x.len() {
+        // 元の位置 index の要素は、すでに前の swap で移動しているかもしれないので辿る
+        let mut index = indices[i];
+        while index < i {
+            index = indices[index];
+        }
+        indices[i] = index;
+        x.swap(i, index);
+    }
+}
+
+/// ベンチマークやテストで使う入力の分布
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Distribution {
+    /// 一様乱数
+    Uniform,
+    /// 昇順に整列済み
+    Sorted,
+    /// 降順に整列済み
+    Reverse,
+    /// 16種類の値だけからなる乱数
+    FewUnique,
+    /// 小さい値ほど多く現れる、指数 1 の Zipf 分布
+    Zipf,
+    /// 前半が昇順、後半が降順
+    OrganPipe,
+}
+
+impl Distribution {
+    pub const ALL: [Distribution; 6] = [
+        Distribution::Uniform,
+        Distribution::Sorted,
+        Distribution::Reverse,
+        Distribution::FewUnique,
+        Distribution::Zipf,
+        Distribution::OrganPipe,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Distribution::Uniform => "uniform",
+            Distribution::Sorted => "sorted",
+            Distribution::Reverse => "reverse",
+            Distribution::FewUnique => "few-unique",
+            Distribution::Zipf => "zipf",
+            Distribution::OrganPipe => "organ-pipe",
+        }
+    }
+}
+
+impl std::str::FromStr for Distribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Distribution::ALL
+            .iter()
+            .find(|d| d.name() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown distribution: {}", s))
+    }
+}
+
+// Zipf 分布で使う値の種類の上限
+const ZIPF_VALUES: usize = 1 << 16;
+
+/// 分布 dist に従う長さ n の列を作る。同じ引数なら毎回同じ列になる
+pub fn new_u32_vec_with(n: usize, dist: Distribution) -> Vec<u32> {
+    match dist {
+        Distribution::Uniform => new_u32_vec(n),
+        Distribution::Sorted => (0..n as u32).collect(),
+        Distribution::Reverse => (0..n as u32).rev().collect(),
+        Distribution::FewUnique => new_u32_vec(n).into_iter().map(|v| v % 16).collect(),
+        Distribution::Zipf => {
+            // 値 k (0 始まり) が現れる重みは 1 / (k + 1)。累積の重みを二分探索して値を選ぶ
+            let mut cumulative = Vec::with_capacity(ZIPF_VALUES);
+            let mut total = 0.0;
+            for k in 0..ZIPF_VALUES.min(n.max(1)) {
+                total += 1.0 / (k + 1) as f64;
+                cumulative.push(total);
+            }
+            let mut rng = Pcg64Mcg::from_seed([0; 16]);
+            (0..n)
+                .map(|_| {
+                    let r = rng.gen::<f64>() * total;
+                    cumulative.partition_point(|&c| c <= r) as u32
+                })
+                .collect()
+        }
+        Distribution::OrganPipe => {
+            let half = (n / 2) as u32;
+            (0..half).chain((0..n as u32 - half).rev()).collect()
+        }
+    }
+}
+
+// third と fourth のテストで使うデータ
+#[cfg(test)]
+pub(crate) mod fixtures {
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct Student {
+        pub(crate) first_name: String,
+        pub(crate) last_name: String,
+        pub(crate) age: u8,
+        pub(crate) club: Option<String>,
+    }
+
+    impl Student {
+        pub(crate) fn new(first_name: &str, last_name: &str, age: u8) -> Student {
+            Student {
+                first_name: first_name.to_string(),
+                last_name: last_name.to_string(),
+                age,
+                club: None,
+            }
+        }
+
+        pub(crate) fn with_club(mut self, club: &str) -> Student {
+            self.club = Some(club.to_string());
+            self
+        }
+    }
+
+    /// Taro、Hanako、Kyoko、Ryosuke の4人。部活に入っているのは Taro と Kyoko だけ
+    pub(crate) fn students() -> [Student; 4] {
+        [
+            Student::new("Taro", "Yamada", 16).with_club("tennis"),
+            Student::new("Hanako", "Yamada", 14),
+            Student::new("Kyoko", "Ito", 15).with_club("chess"),
+            Student::new("Ryosuke", "Hayashi", 17),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_permutation, invert_permutation, is_permutation};
+    use super::{is_sorted_ascending, is_sorted_descending, new_u32_vec_with, Distribution};
+
+    #[test]
+    fn distributions_have_requested_shape() {
+        for dist in Distribution::ALL {
+            assert_eq!(new_u32_vec_with(1001, dist).len(), 1001);
+            assert_eq!(dist.name().parse(), Ok(dist));
+        }
+        assert!(is_sorted_ascending(&new_u32_vec_with(
+            100,
+            Distribution::Sorted
+        )));
+        assert!(is_sorted_descending(&new_u32_vec_with(
+            100,
+            Distribution::Reverse
+        )));
+        assert!(new_u32_vec_with(100, Distribution::FewUnique)
+            .iter()
+            .all(|&v| v < 16));
+
+        let pipe = new_u32_vec_with(9, Distribution::OrganPipe);
+        assert_eq!(pipe, [0, 1, 2, 3, 4, 3, 2, 1, 0]);
+
+        // 0 が最も多く現れる
+        let zipf = new_u32_vec_with(10000, Distribution::Zipf);
+        let zeros = zipf.iter().filter(|&&v| v == 0).count();
+        let ones = zipf.iter().filter(|&&v| v == 1).count();
+        assert!(zeros > ones && ones > 0);
+    }
+
+    #[test]
+    fn permutations_round_trip() {
+        let perm = [2, 0, 3, 1];
+        assert!(is_permutation(&perm));
+        let inverse = invert_permutation(&perm);
+        assert_eq!(inverse, [1, 3, 0, 2]);
+        let mut x = ['a', 'b', 'c', 'd'];
+        apply_permutation(&mut x, &perm);
+        assert_eq!(x, ['c', 'a', 'd', 'b']);
+        apply_permutation(&mut x, &inverse);
+        assert_eq!(x, ['a', 'b', 'c', 'd']);
+        assert!(invert_permutation(&[]).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "permutation")]
+    fn invert_permutation_rejects_duplicates() {
+        invert_permutation(&[0, 0, 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "permutation")]
+    fn invert_permutation_rejects_out_of_range() {
+        invert_permutation(&[0, 3, 1]);
+    }
+}
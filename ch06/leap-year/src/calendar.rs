@@ -0,0 +1,325 @@
+use crate::date::{Date, DateError};
+use crate::month_length;
+
+// 年月日とユリウス通日の変換は Fliegel と Van Flandern の式による。
+// 負の年でも使えるように、負になりうる値の割り算は div_euclid (床関数) で行う
+
+/// 暦
+///
+/// 年は天文学的な数え方で、紀元前1年を0年、紀元前2年を-1年とする
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Calendar {
+    /// 先発グレゴリオ暦。Date と同じ
+    Gregorian,
+    /// 先発ユリウス暦。4で割り切れる年はすべて閏年
+    Julian,
+    /// cutover の前日まではユリウス暦、cutover からはグレゴリオ暦
+    ///
+    /// cutover はグレゴリオ暦で使い始める日。その前のユリウス暦の日付から
+    /// cutover までの間の日付は飛ばされて存在しない。
+    ///
+    /// 0200-03-01 より前はグレゴリオ暦の日付がユリウス暦より遅れていて、切り替えると
+    /// 日付が戻ってしまうので、cutover は [`MIN_CUTOVER`](#associatedconstant.MIN_CUTOVER)
+    /// 以降でなければならない。[`Calendar::mixed`](#method.mixed) で作れば確かめられる
+    Mixed { cutover: Date },
+}
+
+impl Calendar {
+    /// ユリウス暦の1582年10月4日の翌日を、グレゴリオ暦の1582年10月15日とする改暦
+    pub const REFORM_1582: Calendar = Calendar::Mixed {
+        cutover: Date::new_unchecked(1582, 10, 15),
+    };
+
+    /// Mixed の cutover に使える最初の日。この日から 0300-02-28 までは、2つの暦の日付が同じ
+    pub const MIN_CUTOVER: Date = Date::new_unchecked(200, 3, 1);
+
+    /// cutover で改暦する Mixed を作る。cutover が MIN_CUTOVER より前ならエラー
+    pub fn mixed(cutover: Date) -> Result<Calendar, DateError> {
+        if cutover < Self::MIN_CUTOVER {
+            return Err(DateError::InvalidCutover(cutover));
+        }
+        Ok(Calendar::Mixed { cutover })
+    }
+
+    pub fn is_leap_year(&self, year: i32) -> bool {
+        match self.rule_for_month(year, 2) {
+            Calendar::Julian => year.rem_euclid(4) == 0,
+            _ => crate::is_leap_year(year),
+        }
+    }
+
+    /// その月の最後の日。month が 1..=12 でなければ None
+    ///
+    /// Mixed で改暦のある月は、途中の日が飛ばされていても最後の日を返す
+    pub fn days_in_month(&self, year: i32, month: u8) -> Option<u8> {
+        month_length(self.is_leap_year(year), month)
+    }
+
+    /// この暦の年月日をユリウス通日に変換する
+    pub fn to_julian_day_number(&self, year: i32, month: u8, day: u8) -> Result<i64, DateError> {
+        let days = self
+            .days_in_month(year, month)
+            .ok_or(DateError::InvalidMonth(month))?;
+        if day == 0 || day > days {
+            return Err(DateError::InvalidDay { year, month, day });
+        }
+        match *self {
+            Calendar::Gregorian => Ok(gregorian_to_jdn(year, month, day)),
+            Calendar::Julian => Ok(julian_to_jdn(year, month, day)),
+            Calendar::Mixed { cutover } => {
+                let cutover = cutover.julian_day_number();
+                let jdn = julian_to_jdn(year, month, day);
+                if jdn < cutover {
+                    return Ok(jdn);
+                }
+                let jdn = gregorian_to_jdn(year, month, day);
+                if jdn >= cutover {
+                    return Ok(jdn);
+                }
+                Err(DateError::SkippedDay { year, month, day })
+            }
+        }
+    }
+
+    /// この暦の年月日を Date にする
+    pub fn date(&self, year: i32, month: u8, day: u8) -> Result<Date, DateError> {
+        Date::from_julian_day_number(self.to_julian_day_number(year, month, day)?)
+    }
+
+    /// date をこの暦の年月日で表す
+    pub fn ymd(&self, date: &Date) -> (i32, u8, u8) {
+        let jdn = date.julian_day_number();
+        let (year, month, day) = match *self {
+            Calendar::Gregorian => gregorian_from_jdn(jdn),
+            Calendar::Julian => julian_from_jdn(jdn),
+            Calendar::Mixed { cutover } if jdn >= cutover.julian_day_number() => {
+                gregorian_from_jdn(jdn)
+            }
+            Calendar::Mixed { .. } => julian_from_jdn(jdn),
+        };
+        // Date の範囲の日付は、どちらの暦でも i32 の年に収まる
+        (year as i32, month, day)
+    }
+
+    /// この暦の年月日を、暦 to の年月日に変換する
+    pub fn convert(
+        &self,
+        year: i32,
+        month: u8,
+        day: u8,
+        to: &Calendar,
+    ) -> Result<(i32, u8, u8), DateError> {
+        Ok(to.ymd(&self.date(year, month, day)?))
+    }
+
+    // その月に使う暦。Mixed では、翌月の1日をユリウス暦で数えて cutover より後なら
+    // グレゴリオ暦になる
+    fn rule_for_month(&self, year: i32, month: u8) -> Calendar {
+        match *self {
+            Calendar::Mixed { cutover } => {
+                let (year, month) = if month == 12 {
+                    (year.saturating_add(1), 1)
+                } else {
+                    (year, month + 1)
+                };
+                if julian_to_jdn(year, month, 1) <= cutover.julian_day_number() {
+                    Calendar::Julian
+                } else {
+                    Calendar::Gregorian
+                }
+            }
+            calendar => calendar,
+        }
+    }
+}
+
+pub(crate) const fn gregorian_to_jdn(year: i32, month: u8, day: u8) -> i64 {
+    // 3月を年の始めとみなすと、閏日が年の最後に来る
+    let a = (14 - month as i64) / 12;
+    let y = year as i64 + 4800 - a;
+    let m = month as i64 + 12 * a - 3;
+    day as i64 + (153 * m + 2) / 5 + 365 * y + y.div_euclid(4) - y.div_euclid(100)
+        + y.div_euclid(400)
+        - 32045
+}
+
+pub(crate) const fn gregorian_from_jdn(jdn: i64) -> (i64, u8, u8) {
+    let a = jdn + 32044;
+    let b = (4 * a + 3).div_euclid(146097);
+    let c = a - (146097 * b).div_euclid(4);
+    let d = (4 * c + 3) / 1461;
+    let e = c - 1461 * d / 4;
+    let m = (5 * e + 2) / 153;
+    let day = e - (153 * m + 2) / 5 + 1;
+    let month = m + 3 - 12 * (m / 10);
+    (100 * b + d - 4800 + m / 10, month as u8, day as u8)
+}
+
+const fn julian_to_jdn(year: i32, month: u8, day: u8) -> i64 {
+    let a = (14 - month as i64) / 12;
+    let y = year as i64 + 4800 - a;
+    let m = month as i64 + 12 * a - 3;
+    day as i64 + (153 * m + 2) / 5 + 365 * y + y.div_euclid(4) - 32083
+}
+
+const fn julian_from_jdn(jdn: i64) -> (i64, u8, u8) {
+    let c = jdn + 32082;
+    let d = (4 * c + 3).div_euclid(1461);
+    let e = c - (1461 * d).div_euclid(4);
+    let m = (5 * e + 2) / 153;
+    let day = e - (153 * m + 2) / 5 + 1;
+    let month = m + 3 - 12 * (m / 10);
+    (d - 4800 + m / 10, month as u8, day as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Calendar::{self, *};
+    use crate::date::{Date, DateError};
+
+    fn date(s: &str) -> Date {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn known_julian_day_numbers() {
+        for (calendar, (y, m, d), jdn) in [
+            // ユリウス通日の起点
+            (Julian, (-4712, 1, 1), 0),
+            (Gregorian, (-4713, 11, 24), 0),
+            // 紀元前44年3月15日
+            (Julian, (-43, 3, 15), 1705426),
+            (Julian, (1, 1, 1), 1721424),
+            (Gregorian, (1, 1, 1), 1721426),
+            (Julian, (1582, 10, 4), 2299160),
+            (Gregorian, (1582, 10, 15), 2299161),
+            (Julian, (2000, 1, 1), 2451558),
+        ] {
+            assert_eq!(
+                calendar.to_julian_day_number(y, m, d),
+                Ok(jdn),
+                "{:?} {}-{}-{}",
+                calendar,
+                y,
+                m,
+                d
+            );
+        }
+        assert_eq!(Julian.convert(2000, 1, 1, &Gregorian), Ok((2000, 1, 14)));
+        assert_eq!(Gregorian.convert(1, 1, 1, &Julian), Ok((1, 1, 3)));
+    }
+
+    #[test]
+    fn leap_rules() {
+        for (year, gregorian, julian, reform) in [
+            (-400, true, true, true),
+            (-100, false, true, true),
+            (-4, true, true, true),
+            (-1, false, false, false),
+            (0, true, true, true),
+            (1500, false, true, true),
+            (1582, false, false, false),
+            (1600, true, true, true),
+            (1700, false, true, false),
+            (2000, true, true, true),
+        ] {
+            assert_eq!(Gregorian.is_leap_year(year), gregorian, "{}", year);
+            assert_eq!(Julian.is_leap_year(year), julian, "{}", year);
+            assert_eq!(Calendar::REFORM_1582.is_leap_year(year), reform, "{}", year);
+        }
+        // イギリスは1752年に改暦したので、1700年は閏年
+        let britain = Calendar::mixed(date("1752-09-14")).unwrap();
+        assert!(britain.is_leap_year(1700));
+        assert!(!britain.is_leap_year(1800));
+        assert_eq!(britain.days_in_month(1700, 2), Some(29));
+        assert_eq!(Julian.days_in_month(1900, 2), Some(29));
+        assert_eq!(Gregorian.days_in_month(1900, 2), Some(28));
+        assert_eq!(Julian.days_in_month(1900, 13), None);
+    }
+
+    #[test]
+    fn mixed_calendar_skips_days() {
+        let reform = Calendar::REFORM_1582;
+        assert_eq!(reform.date(1582, 10, 4), Ok(date("1582-10-14")));
+        assert_eq!(reform.date(1582, 10, 15), Ok(date("1582-10-15")));
+        for day in 5..15 {
+            assert_eq!(
+                reform.date(1582, 10, day),
+                Err(DateError::SkippedDay {
+                    year: 1582,
+                    month: 10,
+                    day
+                })
+            );
+        }
+        assert_eq!(reform.days_in_month(1582, 10), Some(31));
+        assert_eq!(reform.ymd(&date("1582-10-14")), (1582, 10, 4));
+        assert_eq!(reform.ymd(&date("1582-10-15")), (1582, 10, 15));
+        assert_eq!(
+            reform
+                .date(1582, 10, 4)
+                .unwrap()
+                .days_until(&date("1582-10-15")),
+            1
+        );
+
+        let britain = Calendar::mixed(date("1752-09-14")).unwrap();
+        let before = britain.date(1752, 9, 2).unwrap();
+        let after = britain.date(1752, 9, 14).unwrap();
+        assert_eq!(before.days_until(&after), 1);
+        assert_eq!(britain.convert(1752, 9, 2, &Gregorian), Ok((1752, 9, 13)));
+        assert!(britain.date(1752, 9, 3).is_err());
+        assert_eq!(
+            reform.convert(1700, 2, 29, &britain),
+            Err(DateError::InvalidDay {
+                year: 1700,
+                month: 2,
+                day: 29
+            })
+        );
+    }
+
+    #[test]
+    fn mixed_cutover_must_not_go_back() {
+        for cutover in ["0100-03-01", "-0500-03-01", "0200-02-28"] {
+            let cutover = date(cutover);
+            assert_eq!(
+                Calendar::mixed(cutover),
+                Err(DateError::InvalidCutover(cutover))
+            );
+        }
+        assert_eq!(
+            Calendar::mixed(Calendar::MIN_CUTOVER),
+            Ok(Mixed {
+                cutover: date("0200-03-01")
+            })
+        );
+        assert_eq!(
+            Calendar::mixed(date("1582-10-15")),
+            Ok(Calendar::REFORM_1582)
+        );
+        // 0300-03-01 からは1日ずれているので、改暦で1日飛ばす
+        let early = Calendar::mixed(date("0300-03-01")).unwrap();
+        assert_eq!(early.convert(300, 2, 28, &Gregorian), Ok((300, 2, 28)));
+        assert!(early.date(300, 2, 29).is_err());
+        assert_eq!(early.date(300, 3, 1), Ok(date("0300-03-01")));
+    }
+
+    #[test]
+    fn round_trips_around_year_zero_and_the_reform() {
+        let earliest = Calendar::mixed(Calendar::MIN_CUTOVER).unwrap();
+        for calendar in [Gregorian, Julian, Calendar::REFORM_1582, earliest] {
+            for start in ["-0001-01-01", "0199-01-01", "1580-01-01"] {
+                let start = date(start);
+                let mut prev = calendar.ymd(&start);
+                for n in 1..=2000 {
+                    let d = start.add_days(n).unwrap();
+                    let (y, m, day) = calendar.ymd(&d);
+                    assert!((y, m, day) > prev, "{:?} {}", calendar, d);
+                    assert_eq!(calendar.date(y, m, day), Ok(d), "{:?} {}", calendar, d);
+                    prev = (y, m, day);
+                }
+            }
+        }
+    }
+}
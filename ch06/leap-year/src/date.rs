@@ -0,0 +1,455 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::calendar::{gregorian_from_jdn, gregorian_to_jdn};
+use crate::{days_in_month, days_in_year, is_leap_year};
+
+// 日付の計算はユリウス通日 (紀元前4713年1月1日正午からの日数) を介して行う。
+// 日付と通日の変換は calendar モジュールにある
+
+/// Date で扱える最初と最後の年
+pub const MIN_YEAR: i32 = -9999;
+pub const MAX_YEAR: i32 = 9999;
+
+/// 日付の作成や計算に失敗した
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DateError {
+    /// 年が MIN_YEAR..=MAX_YEAR の範囲にない
+    ///
+    /// 日の加算やユリウス通日からの変換で範囲を出たときは、範囲のすぐ外の年を持つ
+    YearOutOfRange(i64),
+    /// 月が 1..=12 でない
+    InvalidMonth(u8),
+    /// その月にない日
+    InvalidDay { year: i32, month: u8, day: u8 },
+    /// Calendar::Mixed の改暦で飛ばされた日
+    SkippedDay { year: i32, month: u8, day: u8 },
+    /// Calendar::Mixed の改暦の日として使えない日付
+    InvalidCutover(Date),
+    /// [-]YYYY-MM-DD の形式でない
+    InvalidFormat(String),
+}
+
+impl fmt::Display for DateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::DateError::*;
+        match self {
+            YearOutOfRange(year) => write!(
+                f,
+                "year {} is out of range {}..={}",
+                year, MIN_YEAR, MAX_YEAR
+            ),
+            InvalidMonth(month) => write!(f, "invalid month {}", month),
+            InvalidDay { year, month, day } => write!(
+                f,
+                "invalid day {} for {}-{:02}",
+                day,
+                YearDisplay(*year),
+                month
+            ),
+            SkippedDay { year, month, day } => write!(
+                f,
+                "{}-{:02}-{:02} was skipped by the calendar reform",
+                YearDisplay(*year),
+                month,
+                day
+            ),
+            InvalidCutover(date) => write!(
+                f,
+                "{} cannot be a cutover; it must be 0200-03-01 or later",
+                date
+            ),
+            InvalidFormat(s) => write!(f, "'{}' is not a date in [-]YYYY-MM-DD format", s),
+        }
+    }
+}
+
+impl Error for DateError {}
+
+/// 曜日
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    const ALL: [Weekday; 7] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ];
+
+    /// ISO 8601 の曜日番号。月曜日が1、日曜日が7
+    pub fn number_from_monday(self) -> u8 {
+        self as u8 + 1
+    }
+}
+
+impl fmt::Display for Weekday {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// 先発グレゴリオ暦の日付
+///
+/// 年は天文学的な数え方で、紀元前1年を0年、紀元前2年を-1年とする。
+/// 作成時に検査するので、存在しない日付は作れない。年、月、日の順に比較できる。
+/// ほかの暦の年月日との変換は Calendar で行う
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    year: i32,
+    month: u8,
+    day: u8,
+}
+
+impl Date {
+    pub fn new(year: i32, month: u8, day: u8) -> Result<Self, DateError> {
+        if !(MIN_YEAR..=MAX_YEAR).contains(&year) {
+            return Err(DateError::YearOutOfRange(year as i64));
+        }
+        let days = days_in_month(year, month).ok_or(DateError::InvalidMonth(month))?;
+        if day == 0 || day > days {
+            return Err(DateError::InvalidDay { year, month, day });
+        }
+        Ok(Date { year, month, day })
+    }
+
+    // 正しいとわかっている日付を const で作る
+    pub(crate) const fn new_unchecked(year: i32, month: u8, day: u8) -> Self {
+        Date { year, month, day }
+    }
+
+    pub fn year(&self) -> i32 {
+        self.year
+    }
+
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    pub fn is_leap_year(&self) -> bool {
+        is_leap_year(self.year)
+    }
+
+    pub fn weekday(&self) -> Weekday {
+        // ユリウス通日 0 は月曜日
+        Weekday::ALL[self.julian_day_number().rem_euclid(7) as usize]
+    }
+
+    /// 年内の通し日。1月1日が1
+    pub fn ordinal(&self) -> u16 {
+        let before: u16 = (1..self.month)
+            .map(|m| days_in_month(self.year, m).unwrap() as u16)
+            .sum();
+        before + self.day as u16
+    }
+
+    /// ISO 8601 の週番号を (年, 週) で返す
+    ///
+    /// 週は月曜日に始まり、その年の最初の木曜日を含む週が第1週になる。
+    /// そのため年の初めと終わりの数日は、前後の年の週に属することがある
+    pub fn iso_week(&self) -> (i32, u8) {
+        let weekday = self.weekday().number_from_monday() as i32;
+        let week = (self.ordinal() as i32 - weekday + 10) / 7;
+        if week < 1 {
+            (self.year - 1, iso_weeks_in_year(self.year - 1))
+        } else if week > iso_weeks_in_year(self.year) as i32 {
+            (self.year + 1, 1)
+        } else {
+            (self.year, week as u8)
+        }
+    }
+
+    /// other までの日数。other の方が前なら負になる
+    pub fn days_until(&self, other: &Date) -> i64 {
+        other.julian_day_number() - self.julian_day_number()
+    }
+
+    pub fn add_days(&self, days: i64) -> Result<Self, DateError> {
+        Date::from_julian_day_number(self.julian_day_number().saturating_add(days))
+    }
+
+    /// months か月後の日付。その月にない日は月末に切り詰める (1月31日の1か月後は2月28日か29日)
+    pub fn add_months(&self, months: i64) -> Result<Self, DateError> {
+        let index = (self.year as i64 * 12 + self.month as i64 - 1).saturating_add(months);
+        let year = index.div_euclid(12);
+        let month = index.rem_euclid(12) as u8 + 1;
+        if !(MIN_YEAR as i64..=MAX_YEAR as i64).contains(&year) {
+            return Err(DateError::YearOutOfRange(year));
+        }
+        let year = year as i32;
+        let day = self.day.min(days_in_month(year, month).unwrap());
+        Date::new(year, month, day)
+    }
+
+    /// ユリウス通日。2000年1月1日は 2451545
+    pub fn julian_day_number(&self) -> i64 {
+        gregorian_to_jdn(self.year, self.month, self.day)
+    }
+
+    pub fn from_julian_day_number(jdn: i64) -> Result<Self, DateError> {
+        if !(MIN_JDN..=MAX_JDN).contains(&jdn) {
+            let year = if jdn < MIN_JDN {
+                MIN_YEAR as i64 - 1
+            } else {
+                MAX_YEAR as i64 + 1
+            };
+            return Err(DateError::YearOutOfRange(year));
+        }
+        let (year, month, day) = gregorian_from_jdn(jdn);
+        Date::new(year as i32, month, day)
+    }
+}
+
+// Date で扱える最初と最後の日のユリウス通日
+const MIN_JDN: i64 = gregorian_to_jdn(MIN_YEAR, 1, 1);
+const MAX_JDN: i64 = gregorian_to_jdn(MAX_YEAR, 12, 31);
+
+// ISO 8601 の週の数。1月1日が木曜日の年と、1月1日が水曜日の閏年は53週
+fn iso_weeks_in_year(year: i32) -> u8 {
+    // 計算の途中では範囲外の年も使うので、Date を作らずに曜日を求める
+    let jan1 = Date {
+        year,
+        month: 1,
+        day: 1,
+    };
+    match jan1.weekday() {
+        Weekday::Thursday => 53,
+        Weekday::Wednesday if days_in_year(year) == 366 => 53,
+        _ => 52,
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}-{:02}-{:02}",
+            YearDisplay(self.year),
+            self.month,
+            self.day
+        )
+    }
+}
+
+// 年を4桁以上で、負の年は符号を付けて書く
+struct YearDisplay(i32);
+
+impl fmt::Display for YearDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 < 0 {
+            write!(f, "-{:04}", self.0.unsigned_abs())
+        } else {
+            write!(f, "{:04}", self.0)
+        }
+    }
+}
+
+impl FromStr for Date {
+    type Err = DateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DateError::InvalidFormat(s.to_string());
+        let (negative, rest) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let mut parts = rest.split('-');
+        let (year, month, day) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(y), Some(m), Some(d), None) => (y, m, d),
+            _ => return Err(invalid()),
+        };
+        if [year, month, day]
+            .iter()
+            .any(|p| p.is_empty() || !p.bytes().all(|b| b.is_ascii_digit()))
+        {
+            return Err(invalid());
+        }
+        let year: i32 = year.parse().map_err(|_| invalid())?;
+        Date::new(
+            if negative { -year } else { year },
+            month.parse().map_err(|_| invalid())?,
+            day.parse().map_err(|_| invalid())?,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Date, DateError, Weekday};
+    use super::{MAX_JDN, MIN_JDN};
+
+    // Python の datetime (先発グレゴリオ暦) で求めた値
+    // (日付, 曜日, 通し日, ISO 週, ユリウス通日)
+    type Row = (&'static str, Weekday, u16, (i32, u8), i64);
+    const REFERENCE: [Row; 14] = [
+        ("0001-01-01", Weekday::Monday, 1, (1, 1), 1721426),
+        ("1582-10-15", Weekday::Friday, 288, (1582, 41), 2299161),
+        ("1900-02-28", Weekday::Wednesday, 59, (1900, 9), 2415079),
+        ("1900-03-01", Weekday::Thursday, 60, (1900, 9), 2415080),
+        ("1970-01-01", Weekday::Thursday, 1, (1970, 1), 2440588),
+        ("2000-01-01", Weekday::Saturday, 1, (1999, 52), 2451545),
+        ("2000-02-29", Weekday::Tuesday, 60, (2000, 9), 2451604),
+        ("2004-12-31", Weekday::Friday, 366, (2004, 53), 2453371),
+        ("2008-12-29", Weekday::Monday, 364, (2009, 1), 2454830),
+        ("2010-01-03", Weekday::Sunday, 3, (2009, 53), 2455200),
+        ("2021-01-03", Weekday::Sunday, 3, (2020, 53), 2459218),
+        ("2024-02-29", Weekday::Thursday, 60, (2024, 9), 2460370),
+        ("2100-03-01", Weekday::Monday, 60, (2100, 9), 2488129),
+        ("9999-12-31", Weekday::Friday, 365, (9999, 52), 5373484),
+    ];
+
+    fn date(s: &str) -> Date {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn matches_reference_table() {
+        for (s, weekday, ordinal, iso_week, jdn) in REFERENCE {
+            let d = date(s);
+            assert_eq!(d.to_string(), s);
+            assert_eq!(d.weekday(), weekday, "{}", s);
+            assert_eq!(d.ordinal(), ordinal, "{}", s);
+            assert_eq!(d.iso_week(), iso_week, "{}", s);
+            assert_eq!(d.julian_day_number(), jdn, "{}", s);
+            assert_eq!(Date::from_julian_day_number(jdn), Ok(d));
+        }
+        assert_eq!(date("-9999-01-01").julian_day_number(), MIN_JDN);
+        assert_eq!(date("9999-12-31").julian_day_number(), MAX_JDN);
+    }
+
+    #[test]
+    fn round_trips_every_day() {
+        // 400年で暦が一巡するので、1周分の日をすべて確かめる
+        let start = date("1999-12-25");
+        let mut prev = start;
+        for n in 1..=146097 + 10 {
+            let d = start.add_days(n).unwrap();
+            assert!(d > prev);
+            assert_eq!(prev.days_until(&d), 1);
+            assert_eq!(Date::from_julian_day_number(d.julian_day_number()), Ok(d));
+            assert_eq!(d.to_string().parse(), Ok(d));
+            prev = d;
+        }
+    }
+
+    #[test]
+    fn validation() {
+        assert_eq!(
+            Date::new(2023, 2, 29),
+            Err(DateError::InvalidDay {
+                year: 2023,
+                month: 2,
+                day: 29
+            })
+        );
+        assert!(Date::new(2024, 2, 29).is_ok());
+        assert_eq!(Date::new(2024, 13, 1), Err(DateError::InvalidMonth(13)));
+        assert_eq!(
+            Date::new(2024, 4, 0).unwrap_err().to_string(),
+            "invalid day 0 for 2024-04"
+        );
+        assert_eq!(
+            Date::new(-10000, 12, 31),
+            Err(DateError::YearOutOfRange(-10000))
+        );
+        assert_eq!(
+            Date::new(10000, 1, 1),
+            Err(DateError::YearOutOfRange(10000))
+        );
+        for s in [
+            "",
+            "2024",
+            "2024-1",
+            "2024-01-01-01",
+            "2024-+1-01",
+            "--1-01-01",
+            "-",
+            "-0001-02-29",
+            "x-01-01",
+            "2024-01-99999",
+        ] {
+            assert!(s.parse::<Date>().is_err(), "{}", s);
+        }
+        assert_eq!("2024-1-2".parse(), Ok(date("2024-01-02")));
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(date("2000-01-01").days_until(&date("2024-02-29")), 8825);
+        assert_eq!(date("2024-02-29").days_until(&date("2000-01-01")), -8825);
+        assert_eq!(date("2023-12-31").add_days(1), Ok(date("2024-01-01")));
+        assert_eq!(date("2024-03-01").add_days(-1), Ok(date("2024-02-29")));
+        assert_eq!(
+            date("-9999-01-01").add_days(-1),
+            Err(DateError::YearOutOfRange(-10000))
+        );
+        assert_eq!(
+            date("9999-12-31").add_days(1),
+            Err(DateError::YearOutOfRange(10000))
+        );
+        assert!(date("2000-01-01").add_days(i64::MAX).is_err());
+
+        // 月末への切り詰め
+        assert_eq!(date("2024-01-31").add_months(1), Ok(date("2024-02-29")));
+        assert_eq!(date("2023-01-31").add_months(1), Ok(date("2023-02-28")));
+        assert_eq!(date("2024-03-31").add_months(-1), Ok(date("2024-02-29")));
+        assert_eq!(date("2024-05-31").add_months(-13), Ok(date("2023-04-30")));
+        assert_eq!(date("2024-02-29").add_months(12), Ok(date("2025-02-28")));
+        assert_eq!(date("2024-02-29").add_months(48), Ok(date("2028-02-29")));
+        assert_eq!(
+            date("-9999-03-01").add_months(-3),
+            Err(DateError::YearOutOfRange(-10000))
+        );
+        assert!(date("2000-01-01").add_months(i64::MIN).is_err());
+    }
+
+    #[test]
+    fn negative_years() {
+        // 紀元前1年が0年で、先発グレゴリオ暦では閏年
+        let d = date("0000-02-29");
+        assert!(d.is_leap_year());
+        assert_eq!(date("-0001-12-31").add_days(1), Ok(date("0000-01-01")));
+        assert_eq!(d.add_months(-12), Ok(date("-0001-02-28")));
+        assert_eq!(d.ordinal(), 60);
+
+        let d = Date::new(-44, 3, 15).unwrap();
+        assert_eq!(d.to_string(), "-0044-03-15");
+        assert_eq!("-0044-03-15".parse(), Ok(d));
+        assert_eq!(
+            Date::new(-5, 2, 30).unwrap_err().to_string(),
+            "invalid day 30 for -0005-02"
+        );
+
+        // ユリウス通日 0 は月曜日
+        let d = date("-4713-11-24");
+        assert_eq!(d.julian_day_number(), 0);
+        assert_eq!(d.weekday(), Weekday::Monday);
+        assert_eq!(Date::from_julian_day_number(-1), Ok(date("-4713-11-23")));
+        assert!(date("-0001-01-01") < date("0000-01-01"));
+        assert_eq!(date("-0001-01-01").days_until(&date("0001-01-01")), 731);
+    }
+
+    #[test]
+    fn weekday_numbers() {
+        assert_eq!(Weekday::Monday.number_from_monday(), 1);
+        assert_eq!(Weekday::Sunday.number_from_monday(), 7);
+        assert_eq!(Weekday::Friday.to_string(), "Friday");
+    }
+}
@@ -0,0 +1,89 @@
+//! leap-year は先発グレゴリオ暦 (1582年より前にもグレゴリオ暦の規則を当てはめた暦) の
+//! 日付を扱う機能を提供します。
+//!
+//! 閏年の判定 [`is_leap_year`](fn.is_leap_year.html) の上に、日付の型
+//! [`Date`](date/struct.Date.html) を作っています。`Date` は -9999年1月1日から
+//! 9999年12月31日までを表し、曜日、年内の通し日、ISO 8601 の週番号、日付の差、
+//! 日や月の加算、ユリウス通日との変換ができます。
+//!
+//! 年は天文学的な数え方で、紀元前1年を0年とします。ユリウス暦や、ユリウス暦から
+//! グレゴリオ暦に切り替えた暦の年月日は [`Calendar`](calendar/enum.Calendar.html) で扱います。
+
+pub mod calendar;
+pub mod date;
+
+pub use crate::calendar::Calendar;
+pub use crate::date::{Date, DateError, Weekday};
+
+/// 4で割り切れる年は閏年。ただし100で割り切れて400で割り切れない年は平年
+pub fn is_leap_year(year: i32) -> bool {
+    year.rem_euclid(4) == 0 && (year.rem_euclid(100) != 0 || year.rem_euclid(400) == 0)
+}
+
+/// その年の日数
+pub fn days_in_year(year: i32) -> u16 {
+    if is_leap_year(year) {
+        366
+    } else {
+        365
+    }
+}
+
+/// その月の日数。month が 1..=12 でなければ None
+pub fn days_in_month(year: i32, month: u8) -> Option<u8> {
+    month_length(is_leap_year(year), month)
+}
+
+// 閏年かどうかによる月の日数。グレゴリオ暦とユリウス暦で共通
+pub(crate) fn month_length(leap: bool, month: u8) -> Option<u8> {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => Some(31),
+        4 | 6 | 9 | 11 => Some(30),
+        2 if leap => Some(29),
+        2 => Some(28),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{days_in_month, days_in_year, is_leap_year};
+
+    #[test]
+    fn leap_years() {
+        for (year, leap) in [
+            (-400, true),
+            (-100, false),
+            (-4, true),
+            (-1, false),
+            (0, true),
+            (1, false),
+            (4, true),
+            (1600, true),
+            (1700, false),
+            (1900, false),
+            (2000, true),
+            (2023, false),
+            (2024, true),
+            (2100, false),
+        ] {
+            assert_eq!(is_leap_year(year), leap, "year {}", year);
+        }
+        assert_eq!(days_in_year(2000), 366);
+        assert_eq!(days_in_year(1900), 365);
+    }
+
+    #[test]
+    fn month_lengths() {
+        assert_eq!(days_in_month(2024, 2), Some(29));
+        assert_eq!(days_in_month(2023, 2), Some(28));
+        assert_eq!(days_in_month(2023, 4), Some(30));
+        assert_eq!(days_in_month(2023, 12), Some(31));
+        assert_eq!(days_in_month(2023, 0), None);
+        assert_eq!(days_in_month(2023, 13), None);
+        let total: u16 = (1..=12)
+            .map(|m| days_in_month(2024, m).unwrap() as u16)
+            .sum();
+        assert_eq!(total, days_in_year(2024));
+    }
+}
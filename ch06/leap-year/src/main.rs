@@ -1,20 +1,45 @@
 use std::io;
 use std::io::Write;
+use std::process;
 
-fn main() {
-    let mut year = String::new();
-    println!("Please input year to check if it is a leap year");
-    io::stdout().flush().unwrap();
-    io::stdin().read_line(&mut year).unwrap();
-    let year = year.trim().parse::<u32>().unwrap();
+use leap_year::{is_leap_year, Date};
 
-    if is_leap_year(year) {
-        println!("{} is a leap year!", year);
-    } else {
-        println!("{} is not leap year.", year);
+fn main() {
+    println!("Please input a year to check if it is a leap year, or a date (YYYY-MM-DD)");
+    let mut input = String::new();
+    if let Err(e) = io::stdout()
+        .flush()
+        .and_then(|_| io::stdin().read_line(&mut input))
+    {
+        eprintln!("failed to read input: {}", e);
+        process::exit(1);
     }
-}
+    let input = input.trim();
 
-fn is_leap_year(year: u32) -> bool {
-    year % 4 == 0 && !(year % 100 == 0 && year % 400 != 0)
+    // 年だけなら閏年かどうかを、日付なら曜日と週番号を表示する
+    if let Ok(year) = input.parse::<i32>() {
+        if is_leap_year(year) {
+            println!("{} is a leap year!", year);
+        } else {
+            println!("{} is not leap year.", year);
+        }
+        return;
+    }
+    match input.parse::<Date>() {
+        Ok(date) => {
+            let (week_year, week) = date.iso_week();
+            println!(
+                "{} is a {}, day {} of the year, ISO week {}-W{:02}",
+                date,
+                date.weekday(),
+                date.ordinal(),
+                week_year,
+                week
+            );
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
 }